@@ -1,22 +1,41 @@
 pub mod data_parser;
+pub mod file_sender;
+pub mod modem;
 
-use core::str;
 use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use crossbeam::channel::{Receiver, TryRecvError};
 use serialport::{ClearBuffer, SerialPort};
 
 use self::data_parser::parse_line_to_values;
-use crate::shared::serial_read::SerialRead;
-use crate::shared::{Event, SharedData};
+use self::file_sender::{FilePoll, FileSender};
+use crate::shared::ansi;
+use crate::shared::expression::SeriesRef;
+use crate::shared::history::DiskHistory;
+use crate::shared::serial_read::{DerivedKind, DerivedSeries, SentData, SerialRead, Series};
+use crate::shared::statistics::RunningStats;
+use crate::shared::transfer::{FileSend, ModemSend, TransferProgress, TransferState};
+use crate::shared::{Event, QueuedSend, SharedData};
 
 pub struct Backend {
     shared_data: SharedData,
     port: Option<Box<dyn SerialPort>>,
 
+    /// 送信を待っているデータと、送信する時刻。時刻の昇順
+    send_queue: VecDeque<(Instant, QueuedSend)>,
+
+    /// 実行中のファイルの送信
+    file_sender: Option<FileSender>,
+
+    /// 実行中のXMODEMやYMODEMの転送
+    modem: Option<ModemTransfer>,
+
     // receiver for events from the frontend
     event_receiver: Receiver<Event>,
 }
@@ -26,12 +45,16 @@ impl Backend {
         Self {
             shared_data,
             port: None,
+            send_queue: VecDeque::new(),
+            file_sender: None,
+            modem: None,
             event_receiver,
         }
     }
 
     fn reconnect(&mut self) {
-        // 現在のポートをドロップ
+        // 転送中のポートも含め、現在のポートをドロップ
+        self.stop_modem();
         self.port = None;
 
         let port_info = self.shared_data.port_info.read();
@@ -54,6 +77,192 @@ impl Backend {
         }
     }
 
+    /// データを送信する。失敗したら`false`を返す。
+    fn send(&mut self, bytes: &[u8], echo: bool) -> bool {
+        if self.modem.is_some() {
+            *self.shared_data.error_log.lock() =
+                "The port is busy with a file transfer".to_string();
+            return false;
+        }
+        let Some(port) = self.port.as_mut() else {
+            eprintln!("No port selected to send text");
+            *self.shared_data.error_log.lock() = "No port selected to send text".to_string();
+            return false;
+        };
+        if let Err(e) = port.write_all(bytes) {
+            eprintln!("Failed to send text: {e}");
+            *self.shared_data.error_log.lock() = format!("Failed to send text: {e}");
+            return false;
+        }
+        if echo {
+            self.shared_data.read_data.write().push_sent(bytes);
+        }
+        true
+    }
+
+    /// 送信する時刻になったデータを送信する。失敗したら残りは取り消す。
+    fn process_send_queue(&mut self) {
+        let now = Instant::now();
+        while self.send_queue.front().is_some_and(|(at, _)| *at <= now) {
+            let Some((_, queued)) = self.send_queue.pop_front() else {
+                break;
+            };
+            if !self.send(&queued.bytes, queued.echo) {
+                self.send_queue.clear();
+            }
+        }
+        self.shared_data
+            .queued_sends
+            .store(self.send_queue.len(), Ordering::Relaxed);
+    }
+
+    /// 転送を始める前に、他の転送が実行中でないことを確かめてファイルを読み込む。
+    fn read_file_to_send(&self, path: &Path) -> Option<(String, Vec<u8>)> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if self.file_sender.is_some() || self.modem.is_some() {
+            *self.shared_data.error_log.lock() = "A file transfer is already running".to_string();
+            return None;
+        }
+        match std::fs::read(path) {
+            Ok(contents) => Some((name, contents)),
+            Err(e) => {
+                eprintln!("Failed to read {name}: {e}");
+                *self.shared_data.error_log.lock() = format!("Failed to read {name}: {e}");
+                None
+            }
+        }
+    }
+
+    /// ファイルの送信を始める。
+    fn start_file_send(&mut self, options: FileSend) {
+        let Some((name, contents)) = self.read_file_to_send(&options.path) else {
+            return;
+        };
+        let sender = FileSender::new(options, &contents, Instant::now());
+        let (_, total) = sender.progress();
+        *self.shared_data.transfer.write() = Some(TransferProgress::new(name, total));
+        self.file_sender = Some(sender);
+    }
+
+    /// ファイルの送信を進め、進み具合を共有する。
+    fn process_file_send(&mut self) {
+        let Some(sender) = self.file_sender.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        let poll = sender.poll(&self.shared_data.read_data.read(), now);
+        let state = match poll {
+            // 送った量も状態も変わらないので、共有している進み具合はロックしない
+            FilePoll::Wait => return,
+            FilePoll::Send(bytes) => {
+                let echo = sender.options.echo;
+                if self.send(&bytes, echo) {
+                    let line_counter = self.shared_data.read_data.read().line_counter;
+                    if let Some(sender) = self.file_sender.as_mut() {
+                        sender.sent(line_counter, now);
+                    }
+                    TransferState::Running
+                } else {
+                    TransferState::Failed("Failed to write to the port".to_string())
+                }
+            }
+            FilePoll::Done => TransferState::Done,
+            FilePoll::TimedOut => {
+                TransferState::Failed("No response before the timeout".to_string())
+            }
+        };
+        self.finish_transfer(state);
+    }
+
+    /// 転送の進み具合を更新し、終わっていれば後片付けをする。
+    /// 送った量か状態が変わったときだけ呼ぶ。
+    fn finish_transfer(&mut self, state: TransferState) {
+        let mut transfer = self.shared_data.transfer.write();
+        if let (Some(progress), Some(sender)) = (transfer.as_mut(), &self.file_sender) {
+            progress.done = sender.progress().0;
+            progress.state = state.clone();
+        }
+        if state != TransferState::Running {
+            self.file_sender = None;
+        }
+    }
+
+    /// ポートを別のスレッドに渡し、XMODEMやYMODEMでファイルを送る。
+    /// 転送が終わるまで受信したデータはモニタに表示しない。
+    fn start_modem(&mut self, options: ModemSend) {
+        let Some((name, contents)) = self.read_file_to_send(&options.path) else {
+            return;
+        };
+        let Some(mut port) = self.port.take() else {
+            *self.shared_data.error_log.lock() = "No port selected to send a file".to_string();
+            return;
+        };
+        *self.shared_data.transfer.write() =
+            Some(TransferProgress::new(name.clone(), contents.len() as u64));
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let transfer = Arc::clone(&self.shared_data.transfer);
+        let thread_cancel = Arc::clone(&cancel);
+        let handle = thread::spawn(move || {
+            let result = modem::send(
+                &mut port,
+                options.protocol,
+                &name,
+                &contents,
+                &thread_cancel,
+                |sent| {
+                    if let Some(progress) = transfer.write().as_mut() {
+                        progress.done = sent;
+                    }
+                },
+            );
+            if let Some(progress) = transfer.write().as_mut() {
+                progress.state = match result {
+                    Ok(()) => TransferState::Done,
+                    Err(modem::ModemError::Cancelled) => TransferState::Cancelled,
+                    Err(e) => TransferState::Failed(e.to_string()),
+                };
+            }
+            port
+        });
+        self.modem = Some(ModemTransfer { handle, cancel });
+    }
+
+    /// 転送が終わっていれば、ポートを受け取ってモニタを再開する。
+    fn process_modem(&mut self) {
+        if !self
+            .modem
+            .as_ref()
+            .is_some_and(|modem| modem.handle.is_finished())
+        {
+            return;
+        }
+        if let Some(modem) = self.modem.take() {
+            match modem.handle.join() {
+                Ok(port) => self.port = Some(port),
+                Err(_) => {
+                    eprintln!("File transfer thread panicked");
+                    *self.shared_data.error_log.lock() =
+                        "File transfer thread panicked".to_string();
+                    self.reconnect();
+                }
+            }
+        }
+    }
+
+    /// 転送を中止し、スレッドが終わるのを待つ。ポートはドロップする。
+    fn stop_modem(&mut self) {
+        if let Some(modem) = self.modem.take() {
+            modem.cancel.store(true, Ordering::Relaxed);
+            if modem.handle.join().is_err() {
+                eprintln!("File transfer thread panicked");
+            }
+        }
+    }
+
     /// イベントを処理し、スレッドを継続するかどうかを返す
     /// `true`なら継続、`false`なら終了
     fn handle_event(&mut self, event: Event) -> bool {
@@ -88,20 +297,69 @@ impl Backend {
                 }
                 true // 継続
             }
-            Event::ChangeMaxDataPoints(max_data_points) => {
-                self.shared_data.read_data.write().change_max_data_points(max_data_points);
+            Event::ChangeRetention(retention) => {
+                let mut read_data = self.shared_data.read_data.write();
+                read_data.change_retention(retention);
+                self.shared_data.report_history_error(&mut read_data);
                 true // 継続
             }
-            Event::SendText(text) => {
-                if let Some(port) = self.port.as_mut() {
-                    if let Err(e) = port.write_all(text.as_bytes()) {
-                        eprintln!("Failed to send text: {e}");
-                        *self.shared_data.error_log.lock() = format!("Failed to send text: {e}");
-                    }
-                } else {
-                    eprintln!("No port selected to send text");
+            Event::SetDiskHistory(enabled) => {
+                if let Err(e) = self.shared_data.read_data.write().set_disk_history(enabled) {
+                    eprintln!("Failed to create history file: {e}");
                     *self.shared_data.error_log.lock() =
-                        "No port selected to send text".to_string();
+                        format!("Failed to create history file: {e}");
+                }
+                true // 継続
+            }
+            Event::AddDerivedSeries(series) => {
+                self.shared_data
+                    .read_data
+                    .write()
+                    .add_derived_series(series);
+                true // 継続
+            }
+            Event::RemoveDerivedSeries(index) => {
+                self.shared_data
+                    .read_data
+                    .write()
+                    .remove_derived_series(index);
+                true // 継続
+            }
+            Event::SendBytes { bytes, echo } => {
+                self.send(&bytes, echo);
+                true // 継続
+            }
+            Event::QueueSends(sends) => {
+                let mut at = self
+                    .send_queue
+                    .back()
+                    .map_or(Instant::now(), |&(at, _)| at.max(Instant::now()));
+                for queued in sends {
+                    at += queued.delay;
+                    self.send_queue.push_back((at, queued));
+                }
+                self.process_send_queue();
+                true // 継続
+            }
+            Event::CancelQueuedSends => {
+                self.send_queue.clear();
+                self.process_send_queue();
+                true // 継続
+            }
+            Event::SendFile(options) => {
+                self.start_file_send(options);
+                true // 継続
+            }
+            Event::ModemSend(options) => {
+                self.start_modem(options);
+                true // 継続
+            }
+            Event::CancelTransfer => {
+                if let Some(modem) = &self.modem {
+                    // スレッドが受信側に中止を伝えてから終わる
+                    modem.cancel.store(true, Ordering::Relaxed);
+                } else {
+                    self.finish_transfer(TransferState::Cancelled);
                 }
                 true // 継続
             }
@@ -110,6 +368,7 @@ impl Backend {
                 true // 継続
             }
             Event::Shutdown => {
+                self.stop_modem();
                 println!("Shutdown event received. Exiting loop.");
                 false // 終了
             }
@@ -139,12 +398,15 @@ impl Backend {
                     break;
                 }
 
+                self.process_send_queue();
+                self.process_file_send();
+                self.process_modem();
+
                 if let Some(port) = self.port.as_mut() {
                     let mut serial_buf: [u8; 1024] = [0; 1024];
                     match port.read(&mut serial_buf) {
                         Ok(bytes_read) if bytes_read > 0 => {
-                            let received_str = String::from_utf8_lossy(&serial_buf[..bytes_read]);
-                            self.shared_data.read(&received_str);
+                            self.shared_data.read(&serial_buf[..bytes_read]);
                         }
                         Ok(_) => {} // 0バイト読み込み
                         Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
@@ -166,89 +428,246 @@ impl Backend {
     }
 }
 
+/// ポートを占有しているXMODEMやYMODEMの転送スレッド。
+struct ModemTransfer {
+    /// 転送が終わるとポートを返す
+    handle: JoinHandle<Box<dyn SerialPort>>,
+    cancel: Arc<AtomicBool>,
+}
+
 impl SharedData {
-    fn read(&self, s: &str) {
-        self.read_data.write().read(s);
+    fn read(&self, received: &[u8]) {
+        let mut read_data = self.read_data.write();
+        read_data.read(received);
+        self.report_history_error(&mut read_data);
+    }
+
+    /// 履歴ファイルへの書き込みで起きたエラーを表示する。
+    fn report_history_error(&self, read_data: &mut SerialRead) {
+        if let Some(e) = read_data
+            .disk_history
+            .as_mut()
+            .and_then(DiskHistory::take_error)
+        {
+            eprintln!("Failed to write history file: {e}");
+            *self.error_log.lock() = format!("Failed to write history file: {e}");
+        }
     }
 }
 
 impl SerialRead {
     pub fn clear(&mut self) {
         self.raw_data.clear();
-        self.graph_data.clear();
+        self.current_line.clear();
+        self.current_bytes.clear();
+        self.non_utf8_lines.clear();
+        self.offsets.clear();
+        self.received_bytes = 0;
+        self.sent.clear();
         self.timestamps.clear();
+        self.start_time = None;
         self.line_counter = 0;
-        self.raw_data.push_front(String::new());
+
+        // 派生系列の定義は残し、データとフィルタの状態だけを消す
+        self.graph_data.drain(..self.raw_series_count);
+        self.raw_series_count = 0;
+        for series in &mut self.graph_data {
+            series.clear();
+        }
+        for derived in &mut self.derived_series {
+            if let DerivedKind::Filter { filter, .. } = &mut derived.kind {
+                filter.reset();
+            }
+        }
+        self.session_stats = vec![RunningStats::default(); self.derived_series.len()];
+        self.retained_bytes = 0;
+        if let Some(history) = &mut self.disk_history {
+            history.reset();
+        }
+        self.revision += 1;
     }
 
-    fn read(&mut self, mut received_str: &str) {
-        // received_strが空になるまでループ
-        while !received_str.is_empty() {
-            // 1. 先頭から最初の改行まで（または文字列の終わりまで）を一行として切り出す
-            let (is_line_completed, line_end_index) = received_str
-                .find('\n')
+    /// 送信したデータを、受信中の行の手前に表示するよう記録する。
+    fn push_sent(&mut self, bytes: &[u8]) {
+        self.retained_bytes += SerialRead::sent_bytes(bytes);
+        self.sent.push_back(SentData {
+            line: self.line_counter,
+            time: Utc::now(),
+            bytes: bytes.into(),
+        });
+    }
+
+    /// 保持する範囲から押し出された行をディスクに書き出すかどうかを切り替える。
+    /// 無効にするとそれまでに書き出した行は削除される。
+    fn set_disk_history(&mut self, enabled: bool) -> std::io::Result<()> {
+        match (enabled, &self.disk_history) {
+            (true, None) => self.disk_history = Some(DiskHistory::create()?),
+            (false, Some(_)) => self.disk_history = None,
+            _ => {}
+        }
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// 派生系列を追加し、保持している履歴についても値を計算する。
+    fn add_derived_series(&mut self, series: DerivedSeries) {
+        self.derived_series.push(series);
+        self.derived_ids.push(self.next_derived_id);
+        self.next_derived_id += 1;
+
+        // 古い行から順に計算する
+        let first_line = self.first_line();
+        let mut new_series = Series::default();
+        let mut stats = RunningStats::with_missing(first_line);
+        for row in 0..self.timestamps.len() {
+            let line = first_line + row;
+            let time = self.timestamps[row];
+            let mut line_values: Vec<Option<f64>> = self
+                .graph_data
+                .iter()
+                .map(|series| series.get(line))
+                .collect();
+            self.evaluate_derived_series(&mut line_values, time);
+            let value = line_values.last().copied().flatten();
+            if let Some(value) = value {
+                new_series.push(line, value);
+            }
+            stats.push(value, time);
+        }
+
+        self.retained_bytes += new_series.len() * Series::SAMPLE_BYTES;
+        self.graph_data.push(new_series);
+        self.session_stats.push(stats);
+        self.revision += 1;
+    }
+
+    fn remove_derived_series(&mut self, index: usize) {
+        if index < self.derived_series.len() {
+            self.derived_series.remove(index);
+            self.derived_ids.remove(index);
+            let removed = self.graph_data.remove(self.raw_series_count + index);
+            self.session_stats.remove(self.raw_series_count + index);
+            self.retained_bytes = self
+                .retained_bytes
+                .saturating_sub(removed.len() * Series::SAMPLE_BYTES);
+            self.revision += 1;
+        }
+    }
+
+    /// 1行分の値（受信データの系列の値で始まる）に、まだ計算していない派生系列の値を追加する。
+    fn evaluate_derived_series(&mut self, line_values: &mut Vec<Option<f64>>, time: DateTime<Utc>) {
+        let raw_series_count = self.raw_series_count;
+        while line_values.len() < raw_series_count + self.derived_series.len() {
+            let derived_index = line_values.len() - raw_series_count;
+            let resolve = |series: &SeriesRef| match series {
+                // 番号で参照できるのは受信データの系列だけ
+                SeriesRef::Index(index) if *index < raw_series_count => line_values[*index],
+                SeriesRef::Index(_) => None,
+                SeriesRef::Name(name) => {
+                    let index = self.derived_series.iter().position(|s| &s.name == name)?;
+                    line_values.get(raw_series_count + index).copied().flatten()
+                }
+            };
+
+            let value = match &self.derived_series[derived_index].kind {
+                DerivedKind::Expression { expression, .. } => expression.evaluate(&resolve),
+                DerivedKind::Filter { input, .. } => {
+                    let input = resolve(input);
+                    match &mut self.derived_series[derived_index].kind {
+                        DerivedKind::Filter { filter, .. } => filter.apply(input, time),
+                        DerivedKind::Expression { .. } => unreachable!(),
+                    }
+                }
+            };
+            line_values.push(value);
+        }
+    }
+
+    fn read(&mut self, mut received: &[u8]) {
+        // receivedが空になるまでループ
+        while !received.is_empty() {
+            // 1. 先頭から最初の改行まで（またはバイト列の終わりまで）を一行として切り出す
+            let (is_line_completed, line_end_index) = received
+                .iter()
+                .position(|&byte| byte == b'\n')
                 .map(|idx| (true, idx)) // 改行が見つかった場合
-                .unwrap_or((false, received_str.len())); // 見つからなかった場合
+                .unwrap_or((false, received.len())); // 見つからなかった場合
 
-            let line_to_append = &received_str[..line_end_index];
+            let line_to_append = &received[..line_end_index];
             if is_line_completed {
-                received_str = &received_str[line_end_index + 1..];
+                received = &received[line_end_index + 1..];
             } else {
-                received_str = ""; // 文字列の終わりまで処理したので空にする
+                received = &[]; // バイト列の終わりまで処理したので空にする
             }
 
-            // 2. 現在の行バッファ（raw_data[0]）に追記
-            self.raw_data[0].push_str(line_to_append);
+            // 2. 現在の行バッファに追記
+            self.current_bytes.extend_from_slice(line_to_append);
+            self.received_bytes += (line_to_append.len() + usize::from(is_line_completed)) as u64;
 
             // 3. 行が確定した場合（改行が見つかった場合）の処理
             if is_line_completed {
-                // 確定した行をクローンして処理に回す
-                let completed_line = self.raw_data[0].clone();
+                let bytes = std::mem::take(&mut self.current_bytes);
+                let offset = self.received_bytes - bytes.len() as u64 - 1;
+                let line = self.line_counter;
 
-                // パース処理
-                let values = parse_line_to_values(&completed_line);
-                let num_new_series = values.len();
-                let num_existing_series = self.graph_data.len();
-
-                // graph_dataの矩形維持
-                if num_new_series > num_existing_series {
-                    for _ in 0..(num_new_series - num_existing_series) {
-                        let mut new_series = VecDeque::with_capacity(self.max_data_points);
-                        for _ in 0..self.timestamps.len() {
-                            new_series.push_back(None);
-                        }
-                        self.graph_data.push(new_series);
+                // UTF-8として不正な行は、表示用の文字列とは別に受信したバイト列を残す
+                let completed_line = match String::from_utf8(bytes) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        let bytes = e.into_bytes();
+                        let text = String::from_utf8_lossy(&bytes).into_owned();
+                        self.retained_bytes += SerialRead::non_utf8_bytes(&bytes);
+                        self.non_utf8_lines
+                            .push_back((line, bytes.into_boxed_slice()));
+                        text
                     }
-                }
+                };
 
-                // graph_dataの更新
-                for i in 0..self.graph_data.len() {
-                    let value = values.get(i).cloned().flatten();
-                    self.graph_data[i].push_front(value);
-                }
+                // パース処理
+                // 色などのエスケープシーケンスの数字を値と取り違えないよう、取り除いてからパースする
+                let mut values = parse_line_to_values(&ansi::strip(&completed_line));
 
-                // timestampsとカウンタの更新
-                self.timestamps.push_front(Utc::now());
-                self.line_counter += 1;
+                // 新しい系列は派生系列の手前に挿入する。値のない過去の行は埋めなくてよい
+                while self.raw_series_count < values.len() {
+                    self.graph_data
+                        .insert(self.raw_series_count, Series::default());
+                    // 系列が現れる前の行は欠損として数える
+                    self.session_stats
+                        .insert(self.raw_series_count, RunningStats::with_missing(line));
+                    self.raw_series_count += 1;
+                }
 
-                // 新しい空の行を先頭に用意
-                self.raw_data.push_front(String::new());
+                // 派生系列の計算
+                let now = Utc::now();
+                values.resize(self.raw_series_count, None);
+                self.evaluate_derived_series(&mut values, now);
 
-                // リングバッファのサイズ維持
-                let max_points = self.max_data_points;
-                if self.raw_data.len() > max_points + 1 {
-                    self.raw_data.pop_back();
-                }
-                if self.timestamps.len() > max_points {
-                    self.timestamps.pop_back();
-                }
-                for series in &mut self.graph_data {
-                    if series.len() > max_points {
-                        series.pop_back();
+                // graph_dataと統計量の更新
+                for (i, value) in values.into_iter().enumerate() {
+                    if let Some(value) = value {
+                        self.graph_data[i].push(line, value);
+                        self.retained_bytes += Series::SAMPLE_BYTES;
                     }
+                    self.session_stats[i].push(value, now);
                 }
+
+                // raw_data、timestampsとカウンタの更新
+                self.retained_bytes += SerialRead::line_bytes(&completed_line);
+                self.raw_data.push_back(completed_line);
+                self.offsets.push_back(offset);
+                self.timestamps.push_back(now);
+                self.start_time.get_or_insert(now);
+                self.line_counter += 1;
+                self.revision += 1;
+
+                // 保持する範囲を超えた古い行を捨てる
+                self.apply_retention();
             }
         }
+
+        // 複数バイトの文字が読み込みの境目で分かれても正しく表示できるよう、
+        // 受信中の行はバイト列からまとめて変換し直す
+        self.current_line = String::from_utf8_lossy(&self.current_bytes).into_owned();
     }
 }
 
@@ -256,8 +675,14 @@ impl SerialRead {
 mod tests {
     use std::sync::Arc;
 
+    use chrono::TimeDelta;
+
     use super::*;
+    use crate::shared::expression::Expr;
+    use crate::shared::filter::{FilterKind, SignalFilter};
+    use crate::shared::history::SeriesId;
     use crate::shared::port_info::PortsInfo;
+    use crate::shared::serial_read::RetentionPolicy;
     use crossbeam::channel;
     use parking_lot::{Mutex, RwLock};
 
@@ -272,6 +697,8 @@ mod tests {
                 baud_rate: 115200,
             })),
             error_log: Arc::new(Mutex::new(String::new())),
+            queued_sends: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            transfer: Arc::new(RwLock::new(None)),
         };
         let backend = Backend::new(shared_data.clone(), rx);
         (backend, shared_data, tx)
@@ -292,7 +719,7 @@ mod tests {
 
         {
             let mut read_data = shared_data.read_data.write();
-            read_data.read("1,2,3\n");
+            read_data.read(b"1,2,3\n");
         }
 
         {
@@ -309,8 +736,8 @@ mod tests {
             let read_data = shared_data.read_data.read();
             assert_eq!(read_data.line_counter, 0);
             assert!(read_data.graph_data.is_empty());
-            assert_eq!(read_data.raw_data.len(), 1);
-            assert_eq!(read_data.raw_data[0], "");
+            assert!(read_data.raw_data.is_empty());
+            assert_eq!(read_data.current_line, "");
         }
 
         tx.send(Event::Shutdown).unwrap();
@@ -321,31 +748,297 @@ mod tests {
     fn test_serial_read_logic() {
         let mut read_data = SerialRead::new(10);
 
-        read_data.read("1.1,2.2\n");
+        read_data.read(b"1.1,2.2\n");
         assert_eq!(read_data.line_counter, 1);
-        assert_eq!(read_data.raw_data.len(), 2);
-        assert_eq!(read_data.raw_data[1], "1.1,2.2");
+        assert_eq!(read_data.raw_data.len(), 1);
+        assert_eq!(read_data.raw_data[0], "1.1,2.2");
         assert_eq!(read_data.graph_data.len(), 2);
-        assert_eq!(read_data.graph_data[0][0], Some(1.1));
-        assert_eq!(read_data.graph_data[1][0], Some(2.2));
+        assert_eq!(read_data.graph_data[0].get(0), Some(1.1));
+        assert_eq!(read_data.graph_data[1].get(0), Some(2.2));
         assert_eq!(read_data.timestamps.len(), 1);
 
-        read_data.read("3.3,4.4,5.5\n");
+        read_data.read(b"3.3,4.4,5.5\n");
         assert_eq!(read_data.line_counter, 2);
         assert_eq!(read_data.graph_data.len(), 3);
-        assert_eq!(read_data.graph_data[0][1], Some(1.1));
-        assert_eq!(read_data.graph_data[1][1], Some(2.2));
-        assert_eq!(read_data.graph_data[2][1], None);
-        assert_eq!(read_data.graph_data[0][0], Some(3.3));
-        assert_eq!(read_data.graph_data[1][0], Some(4.4));
-        assert_eq!(read_data.graph_data[2][0], Some(5.5));
-
-        read_data.read("6.6\n");
+        assert_eq!(read_data.graph_data[0].get(0), Some(1.1));
+        assert_eq!(read_data.graph_data[1].get(0), Some(2.2));
+        assert_eq!(read_data.graph_data[2].get(0), None);
+        assert_eq!(read_data.graph_data[0].get(1), Some(3.3));
+        assert_eq!(read_data.graph_data[1].get(1), Some(4.4));
+        assert_eq!(read_data.graph_data[2].get(1), Some(5.5));
+
+        read_data.read(b"6.6\n");
         assert_eq!(read_data.line_counter, 3);
         assert_eq!(read_data.graph_data.len(), 3);
-        assert_eq!(read_data.graph_data[0][0], Some(6.6));
-        assert_eq!(read_data.graph_data[1][0], None);
-        assert_eq!(read_data.graph_data[2][0], None);
+        assert_eq!(read_data.graph_data[0].get(2), Some(6.6));
+        assert_eq!(read_data.graph_data[1].get(2), None);
+        assert_eq!(read_data.graph_data[2].get(2), None);
+    }
+
+    #[test]
+    fn test_serial_read_ansi_escapes() {
+        let mut read_data = SerialRead::new(10);
+
+        // 色のエスケープシーケンスの数字は値にしない。表示用に生の行は残す
+        read_data.read(b"\x1b[32m1.5\x1b[0m\n");
+        assert_eq!(read_data.graph_data.len(), 1);
+        assert_eq!(read_data.graph_data[0].get(0), Some(1.5));
+        assert_eq!(read_data.raw_data[0], "\x1b[32m1.5\x1b[0m");
+
+        // 2回の読み込みにまたがったシーケンスも取り除かれる
+        read_data.read(b"\x1b[3");
+        read_data.read(b"1m2.5,\x1b[0m3.5\n");
+        assert_eq!(read_data.graph_data.len(), 2);
+        assert_eq!(read_data.graph_data[0].get(1), Some(2.5));
+        assert_eq!(read_data.graph_data[1].get(1), Some(3.5));
+
+        // `\r`で書き直された行は、表示では最後に書かれた内容だけになる
+        read_data.read(b"10%\r20%\r30%\r\n");
+        assert_eq!(read_data.raw_data[2], "10%\r20%\r30%\r");
+        assert_eq!(
+            ansi::rewrite_carriage_returns(&read_data.raw_data[2]),
+            "30%"
+        );
+    }
+
+    #[test]
+    fn test_derived_series() {
+        let mut read_data = SerialRead::new(10);
+        read_data.read(b"3,4\n");
+
+        let expression = |name: &str, source: &str| DerivedSeries {
+            name: name.to_string(),
+            kind: DerivedKind::Expression {
+                source: source.to_string(),
+                expression: Expr::parse(source).unwrap(),
+            },
+        };
+        read_data.add_derived_series(expression("norm", "sqrt($1^2 + $2^2)"));
+        read_data.add_derived_series(expression("double", "norm * 2"));
+        // 追加前の履歴も計算される
+        assert_eq!(read_data.graph_data.len(), 4);
+        assert_eq!(read_data.graph_data[2].get(0), Some(5.0));
+        assert_eq!(read_data.graph_data[3].get(0), Some(10.0));
+
+        // 新しい受信系列は派生系列の手前に入る
+        read_data.read(b"6,8,1\n");
+        assert_eq!(read_data.raw_series_count, 3);
+        assert_eq!(read_data.series_name(2), "Series 3");
+        assert_eq!(read_data.series_name(3), "norm");
+        assert_eq!(read_data.graph_data[2].get(1), Some(1.0));
+        assert_eq!(read_data.graph_data[2].get(0), None);
+        assert_eq!(read_data.graph_data[3].get(1), Some(10.0));
+        assert_eq!(read_data.graph_data[4].get(1), Some(20.0));
+
+        read_data.remove_derived_series(0);
+        read_data.read(b"1\n");
+        assert_eq!(read_data.graph_data.len(), 4);
+        assert_eq!(read_data.graph_data[3].get(2), None); // 参照先が消えた
+    }
+
+    #[test]
+    fn test_series_numbers_refer_to_received_columns() {
+        let mut read_data = SerialRead::new(10);
+        read_data.read(b"3\n");
+
+        let expression = |name: &str, source: &str| DerivedSeries {
+            name: name.to_string(),
+            kind: DerivedKind::Expression {
+                source: source.to_string(),
+                expression: Expr::parse(source).unwrap(),
+            },
+        };
+        read_data.add_derived_series(expression("scaled", "$1 * 10"));
+        read_data.add_derived_series(expression("second", "$2 + 1"));
+        // $2はまだない受信データの系列で、位置が同じ派生系列ではない
+        assert_eq!(read_data.graph_data[2].get(0), None);
+
+        // 受信データの系列が増えても同じ列を参照し続ける
+        read_data.read(b"4,5\n");
+        assert_eq!(read_data.series_name(1), "Series 2");
+        assert_eq!(read_data.graph_data[2].get(1), Some(40.0));
+        assert_eq!(read_data.graph_data[3].get(1), Some(6.0));
+    }
+
+    #[test]
+    fn test_filtered_series() {
+        let mut read_data = SerialRead::new(10);
+        read_data.read(b"1\n3\n");
+
+        read_data.add_derived_series(DerivedSeries {
+            name: "smooth".to_string(),
+            kind: DerivedKind::Filter {
+                input: SeriesRef::Index(0),
+                filter: SignalFilter::new(FilterKind::MovingAverage { window: 2 }),
+            },
+        });
+        // 履歴にもフィルタがかかり、状態は新しい行に引き継がれる
+        assert_eq!(read_data.graph_data[1].get(0), Some(1.0));
+        assert_eq!(read_data.graph_data[1].get(1), Some(2.0));
+
+        read_data.read(b"5\n");
+        assert_eq!(read_data.graph_data[1].get(2), Some(4.0));
+    }
+
+    #[test]
+    fn test_clear_resets_filters() {
+        let mut read_data = SerialRead::new(10);
+        read_data.add_derived_series(DerivedSeries {
+            name: "integral".to_string(),
+            kind: DerivedKind::Filter {
+                input: SeriesRef::Index(0),
+                filter: SignalFilter::new(FilterKind::Integral),
+            },
+        });
+
+        // タイムスタンプに差が出るよう、間を空けて2行受信する
+        read_data.read(b"1\n");
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        read_data.read(b"1\n");
+        assert!(read_data.graph_data[1].get(1).unwrap() > 0.0);
+
+        // クリア後は積分が0からやり直しになる
+        read_data.clear();
+        read_data.read(b"1\n");
+        assert_eq!(read_data.graph_data[1].get(0), Some(0.0));
+    }
+
+    #[test]
+    fn test_session_stats_survive_truncation() {
+        let mut read_data = SerialRead::new(2);
+
+        read_data.read(b"1\n2\n3,10\n4\n");
+        assert_eq!(read_data.graph_data[0].len(), 2);
+        assert_eq!(read_data.first_line(), 2);
+
+        let stats = &read_data.session_stats[0];
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 4.0);
+        assert_eq!(stats.mean(), Some(2.5));
+        assert_eq!(stats.last, Some(4.0));
+
+        // 3行目で現れた系列は、それ以前と4行目を欠損として数える
+        let stats = &read_data.session_stats[1];
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.missing, 3);
+    }
+
+    #[test]
+    fn test_sent_data_is_not_parsed() {
+        let mut read_data = SerialRead::new(2);
+        read_data.read(b"1\n2");
+        read_data.push_sent(b"3\n");
+        read_data.read(b"\n4\n");
+
+        // 送信したデータは行にも系列にも含めず、送信したときに受信中だった行の手前に置く
+        assert_eq!(read_data.line_counter, 3);
+        assert_eq!(read_data.graph_data[0].len(), 2);
+        assert_eq!(read_data.sent.len(), 1);
+        assert_eq!(read_data.sent[0].line, 1);
+
+        // 後ろの行が捨てられたら一緒に捨てる
+        read_data.read(b"5\n");
+        assert!(read_data.sent.is_empty());
+    }
+
+    #[test]
+    fn test_change_retention_keeps_newest() {
+        let mut read_data = SerialRead::new(10);
+        read_data.read(b"1\n2\n3,30\n4\n5\n");
+
+        read_data.change_retention(RetentionPolicy::Lines(2));
+        assert_eq!(read_data.raw_data, ["4", "5"]);
+        assert_eq!(read_data.first_line(), 3);
+        assert_eq!(
+            read_data.graph_data[0].iter().collect::<Vec<_>>(),
+            [(3, 4.0), (4, 5.0)]
+        );
+        // 残った行に値がなければ系列は空になる
+        assert!(read_data.graph_data[1].is_empty());
+        assert_eq!(read_data.timestamp(4), read_data.timestamps.back().copied());
+    }
+
+    #[test]
+    fn test_time_and_size_retention() {
+        let mut read_data = SerialRead::new(100);
+        read_data.read(b"1\n2\n3\n4\n");
+
+        // 1秒間隔で確定したことにする
+        let start = read_data.timestamps[0];
+        for (i, time) in read_data.timestamps.iter_mut().enumerate() {
+            *time = start + TimeDelta::seconds(i as i64);
+        }
+        read_data.change_retention(RetentionPolicy::Duration(TimeDelta::seconds(2)));
+        assert_eq!(read_data.raw_data, ["2", "3", "4"]);
+
+        // 1行ずつ増減し、消えた行の分だけ減る
+        let bytes = read_data.retained_bytes;
+        assert_eq!(
+            bytes,
+            3 * (SerialRead::line_bytes("1") + Series::SAMPLE_BYTES)
+        );
+        read_data.change_retention(RetentionPolicy::Megabytes(0));
+        assert!(read_data.raw_data.is_empty());
+        assert_eq!(read_data.retained_bytes, 0);
+    }
+
+    #[test]
+    fn test_disk_history() {
+        let mut read_data = SerialRead::new(2);
+        read_data.read(b"1\n");
+        read_data.set_disk_history(true).unwrap();
+        read_data.read(b"2,20\n3\n4\n");
+
+        // メモリに残るのは最新2行。押し出された行はディスクから読める
+        assert_eq!(read_data.first_line(), 2);
+        assert_eq!(read_data.oldest_line(), 0);
+        let history = read_data.disk_history.as_ref().unwrap();
+        assert_eq!(history.lines(), 0..2);
+        let lines = history.read_lines(0..2).unwrap();
+        assert_eq!(lines[0].text, "1");
+        assert_eq!(
+            lines[1].values,
+            [(SeriesId::Raw(0), 2.0), (SeriesId::Raw(1), 20.0)]
+        );
+
+        read_data.clear();
+        assert!(read_data.disk_history.as_ref().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_disk_history_survives_series_layout_changes() {
+        let mut read_data = SerialRead::new(1);
+        read_data.set_disk_history(true).unwrap();
+        read_data.add_derived_series(DerivedSeries {
+            name: "double".to_string(),
+            kind: DerivedKind::Expression {
+                source: "$1 * 2".to_string(),
+                expression: Expr::parse("$1 * 2").unwrap(),
+            },
+        });
+        read_data.read(b"1\n2\n");
+
+        // 書き出した後で受信データの系列が増え、派生系列の位置がずれる
+        read_data.read(b"3,30\n");
+        assert_eq!(read_data.series_name(2), "double");
+        let lines = read_data
+            .disk_history
+            .as_ref()
+            .unwrap()
+            .read_lines(0..1)
+            .unwrap();
+        let value = |index: usize| {
+            let id = read_data.series_id(index);
+            lines[0]
+                .values
+                .iter()
+                .find(|&&(series, _)| series == id)
+                .map(|&(_, value)| value)
+        };
+        assert_eq!(value(0), Some(1.0));
+        assert_eq!(value(1), None);
+        assert_eq!(value(2), Some(2.0));
     }
 
     #[test]
@@ -353,18 +1046,46 @@ mod tests {
         let mut read_data = SerialRead::new(10);
 
         // 1. 途中で途切れたデータを受信
-        read_data.read("1,2\n3,");
+        read_data.read(b"1,2\n3,");
         assert_eq!(read_data.line_counter, 1);
-        assert_eq!(read_data.raw_data.len(), 2);
-        assert_eq!(read_data.raw_data[1], "1,2");
-        assert_eq!(read_data.raw_data[0], "3,"); // 未完了行がバッファに残る
+        assert_eq!(read_data.raw_data.len(), 1);
+        assert_eq!(read_data.raw_data[0], "1,2");
+        assert_eq!(read_data.current_line, "3,"); // 未完了行がバッファに残る
 
         // 2. 残りのデータを受信
-        read_data.read("4\n5,6\n");
+        read_data.read(b"4\n5,6\n");
         assert_eq!(read_data.line_counter, 3);
-        assert_eq!(read_data.raw_data.len(), 4);
-        assert_eq!(read_data.raw_data[1], "5,6"); // 最新の完了行
-        assert_eq!(read_data.raw_data[2], "3,4"); // 結合された行
-        assert_eq!(read_data.raw_data[0], ""); // 完了しているのでバッファは空
+        assert_eq!(read_data.raw_data.len(), 3);
+        assert_eq!(read_data.raw_data[2], "5,6"); // 最新の完了行
+        assert_eq!(read_data.raw_data[1], "3,4"); // 結合された行
+        assert_eq!(read_data.current_line, ""); // 完了しているのでバッファは空
+    }
+
+    #[test]
+    fn test_serial_read_keeps_raw_bytes() {
+        let mut read_data = SerialRead::new(2);
+        read_data.set_disk_history(true).unwrap();
+
+        // 「あ」(E3 81 82) が読み込みの境目で分かれても1文字として読める
+        read_data.read(b"\xe3\x81");
+        assert_eq!(read_data.offset(0), Some(0));
+        read_data.read(b"\x82\n\x1b\xff1\n");
+        assert_eq!(read_data.raw_data, ["あ", "\x1b\u{FFFD}1"]);
+        assert_eq!(read_data.bytes(0), Some(&b"\xe3\x81\x82"[..]));
+        assert_eq!(read_data.bytes(1), Some(&b"\x1b\xff1"[..]));
+        assert_eq!(read_data.offset(1), Some(4));
+        assert_eq!(read_data.offset(2), Some(8));
+
+        // 押し出された行もバイト列と位置がディスクに残る
+        read_data.read(b"2\n3\n");
+        assert!(read_data.non_utf8_lines.is_empty());
+        let lines = read_data
+            .disk_history
+            .as_ref()
+            .unwrap()
+            .read_lines(0..2)
+            .unwrap();
+        assert_eq!(lines[1].bytes, b"\x1b\xff1");
+        assert_eq!(lines[1].offset, 4);
     }
 }
@@ -0,0 +1,151 @@
+// src/backend/file_sender.rs
+
+use std::time::Instant;
+
+use crate::shared::ansi;
+use crate::shared::serial_read::SerialRead;
+use crate::shared::transfer::FileSend;
+
+/// 次に何をするか。
+#[derive(Debug, PartialEq)]
+pub enum FilePoll {
+    /// 待ち時間か応答を待っている
+    Wait,
+    /// このデータを送信し、送信したら`FileSender::sent`を呼ぶ
+    Send(Vec<u8>),
+    Done,
+    /// 応答が来なかった
+    TimedOut,
+}
+
+/// ファイルを設定に従って少しずつ送信する状態。
+pub struct FileSender {
+    pub options: FileSend,
+    units: Vec<Vec<u8>>,
+    /// 次に送信する単位の添字
+    next: usize,
+    /// 送信済みのバイト数と全体のバイト数
+    done: u64,
+    total: u64,
+    /// 次の単位を送信してよい時刻
+    next_at: Instant,
+    /// 応答を待っている場合、送信したときに受信中だった行の行番号と待つ期限
+    waiting: Option<(usize, Instant)>,
+}
+
+impl FileSender {
+    pub fn new(options: FileSend, contents: &[u8], now: Instant) -> Self {
+        let units = options.split(contents);
+        Self {
+            total: units.iter().map(|unit| unit.len() as u64).sum(),
+            units,
+            options,
+            next: 0,
+            done: 0,
+            next_at: now,
+            waiting: None,
+        }
+    }
+
+    /// 送信済みのバイト数と全体のバイト数。
+    pub fn progress(&self) -> (u64, u64) {
+        (self.done, self.total)
+    }
+
+    pub fn poll(&mut self, read_data: &SerialRead, now: Instant) -> FilePoll {
+        if let Some((line, deadline)) = self.waiting {
+            let first_line = read_data.first_line();
+            let responded = self.options.wait_for.as_ref().is_none_or(|pattern| {
+                (line.max(first_line)..read_data.line_counter).any(|line| {
+                    pattern.is_match(&ansi::strip(&read_data.raw_data[line - first_line]))
+                })
+            });
+            if responded {
+                self.waiting = None;
+                self.next_at = now + self.options.delay;
+            } else if now >= deadline {
+                return FilePoll::TimedOut;
+            } else {
+                // 調べ終わった行は次に調べない
+                self.waiting = Some((read_data.line_counter.max(line), deadline));
+                return FilePoll::Wait;
+            }
+        }
+
+        if self.next >= self.units.len() {
+            FilePoll::Done
+        } else if now < self.next_at {
+            FilePoll::Wait
+        } else {
+            FilePoll::Send(self.units[self.next].clone())
+        }
+    }
+
+    /// `poll`が返したデータを送信した。`line_counter`は送信したときに受信中だった行の行番号。
+    pub fn sent(&mut self, line_counter: usize, now: Instant) {
+        self.done += self.units[self.next].len() as u64;
+        self.next += 1;
+        if self.options.wait_for.is_some() {
+            self.waiting = Some((line_counter, now + self.options.timeout));
+        } else {
+            self.next_at = now + self.options.delay;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use regex::Regex;
+
+    use super::*;
+    use crate::shared::transfer::FileSendUnit;
+
+    #[test]
+    fn test_wait_for_response() {
+        let options = FileSend {
+            path: PathBuf::new(),
+            unit: FileSendUnit::Lines {
+                line_ending: b"\n".to_vec(),
+            },
+            delay: Duration::from_millis(10),
+            wait_for: Some(Regex::new("^ok").unwrap()),
+            timeout: Duration::from_secs(1),
+            echo: false,
+        };
+        let start = Instant::now();
+        let mut sender = FileSender::new(options, b"G28\nG1 X10\n", start);
+        let mut read_data = SerialRead::new(10);
+
+        assert_eq!(
+            sender.poll(&read_data, start),
+            FilePoll::Send(b"G28\n".to_vec())
+        );
+        sender.sent(read_data.line_counter, start);
+        assert_eq!(sender.progress(), (4, 11));
+
+        // 応答が来るまでは送らない
+        read_data.read(b"busy\no");
+        assert_eq!(sender.poll(&read_data, start), FilePoll::Wait);
+        read_data.read(b"k\n");
+        // 応答の後は待ち時間を置く
+        assert_eq!(sender.poll(&read_data, start), FilePoll::Wait);
+        let later = start + Duration::from_millis(10);
+        assert_eq!(
+            sender.poll(&read_data, later),
+            FilePoll::Send(b"G1 X10\n".to_vec())
+        );
+        sender.sent(read_data.line_counter, later);
+
+        // 前の応答では次に進まず、期限を過ぎたら失敗する
+        assert_eq!(sender.poll(&read_data, later), FilePoll::Wait);
+        assert_eq!(
+            sender.poll(&read_data, later + Duration::from_secs(1)),
+            FilePoll::TimedOut
+        );
+        read_data.read(b"ok\n");
+        assert_eq!(sender.poll(&read_data, later), FilePoll::Done);
+    }
+}
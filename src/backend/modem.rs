@@ -0,0 +1,480 @@
+// src/backend/modem.rs
+
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::shared::transfer::ModemProtocol;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// CRC-16を使う受信側が送る開始の合図
+const CRC_REQUEST: u8 = b'C';
+/// データの最後のブロックの埋め草
+const PAD: u8 = 0x1a;
+
+/// 1ブロックを送り直す最大の回数
+const MAX_RETRIES: usize = 10;
+/// 受信側が開始するのを待つ時間
+const START_TIMEOUT: Duration = Duration::from_secs(60);
+/// ブロックへの応答を待つ時間
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 転送が失敗した理由。
+#[derive(Debug)]
+pub enum ModemError {
+    /// こちらで中止した
+    Cancelled,
+    /// 受信側がCANで中止した
+    CancelledByReceiver,
+    Timeout(&'static str),
+    TooManyRetries,
+    /// YMODEMのファイル名とサイズがブロック0に収まらない
+    NameTooLong,
+    Io(io::Error),
+}
+
+impl std::fmt::Display for ModemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModemError::Cancelled => write!(f, "Cancelled"),
+            ModemError::CancelledByReceiver => write!(f, "Cancelled by the receiver"),
+            ModemError::Timeout(waiting_for) => write!(f, "Timed out waiting for {waiting_for}"),
+            ModemError::TooManyRetries => {
+                write!(f, "The receiver rejected a block {MAX_RETRIES} times")
+            }
+            ModemError::NameTooLong => write!(f, "The file name is too long for YMODEM"),
+            ModemError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<io::Error> for ModemError {
+    fn from(e: io::Error) -> Self {
+        ModemError::Io(e)
+    }
+}
+
+/// XMODEM-CRCで使うCRC-16/XMODEM（多項式0x1021、初期値0）。
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// ブロック番号`number`のパケット。`data`がブロックの大きさに満たなければ`pad`で埋める。
+fn packet(number: u8, data: &[u8], size: usize, pad: u8) -> Vec<u8> {
+    let mut block = data.to_vec();
+    block.resize(size, pad);
+    let crc = crc16(&block);
+
+    let mut packet = Vec::with_capacity(size + 5);
+    packet.push(if size == 1024 { STX } else { SOH });
+    packet.extend([number, !number]);
+    packet.extend(block);
+    packet.extend(crc.to_be_bytes());
+    packet
+}
+
+/// YMODEMのブロック0。ファイル名とサイズをNULで区切り、NULで埋める。
+/// 128バイトに収まらなければ1024バイトのブロックを使う。
+fn header_packet(name: &str, size: usize) -> Result<Vec<u8>, ModemError> {
+    let header = format!("{name}\0{size}");
+    // 後ろに少なくとも1つNULが残るようにする
+    match header.len() {
+        len if len < 128 => Ok(packet(0, header.as_bytes(), 128, 0)),
+        len if len < 1024 => Ok(packet(0, header.as_bytes(), 1024, 0)),
+        _ => Err(ModemError::NameTooLong),
+    }
+}
+
+/// ポートを占有して、受信側とやり取りしながらファイルを送る。
+struct Sender<'a, P> {
+    port: &'a mut P,
+    cancel: &'a AtomicBool,
+}
+
+impl<P: Read + Write> Sender<'_, P> {
+    /// 1バイト受信する。期限までに受信しなければ`None`。
+    fn read_byte(&mut self, deadline: Instant) -> Result<Option<u8>, ModemError> {
+        let mut byte = [0];
+        while Instant::now() < deadline {
+            if self.cancel.load(Ordering::Relaxed) {
+                return Err(ModemError::Cancelled);
+            }
+            match self.port.read(&mut byte) {
+                Ok(1) => return Ok(Some(byte[0])),
+                Ok(_) => {}
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    /// 受信側がCRCモードでの開始を求めるのを待つ。それまでに届いたバイトは読み捨てる。
+    fn wait_for_start(&mut self) -> Result<(), ModemError> {
+        let deadline = Instant::now() + START_TIMEOUT;
+        loop {
+            match self.read_byte(deadline)? {
+                Some(CRC_REQUEST) => return Ok(()),
+                Some(CAN) => return Err(ModemError::CancelledByReceiver),
+                Some(_) => {}
+                None => return Err(ModemError::Timeout("the receiver to start")),
+            }
+        }
+    }
+
+    /// ACKが返るまでパケットを送り直す。
+    fn send_packet(&mut self, packet: &[u8]) -> Result<(), ModemError> {
+        for _ in 0..MAX_RETRIES {
+            self.port.write_all(packet)?;
+            self.port.flush()?;
+            let deadline = Instant::now() + RESPONSE_TIMEOUT;
+            loop {
+                match self.read_byte(deadline)? {
+                    Some(ACK) => return Ok(()),
+                    Some(CAN) => return Err(ModemError::CancelledByReceiver),
+                    // NAKか応答がなければ送り直す
+                    Some(NAK) | None => break,
+                    // 受信側の表示などは読み捨てる
+                    Some(_) => {}
+                }
+            }
+        }
+        Err(ModemError::TooManyRetries)
+    }
+
+    /// データのブロックを順に送り、EOTで終える。`progress`には送信済みのバイト数を渡す。
+    fn send_data(
+        &mut self,
+        data: &[u8],
+        block_size: usize,
+        progress: &mut impl FnMut(u64),
+    ) -> Result<(), ModemError> {
+        let mut sent = 0;
+        for (i, chunk) in data.chunks(block_size).enumerate() {
+            // ブロック番号は1から始まり、255の次は0に戻る
+            let number = (i + 1) as u8;
+            self.send_packet(&packet(number, chunk, block_size, PAD))?;
+            sent += chunk.len() as u64;
+            progress(sent);
+        }
+        // YMODEMの受信側は最初のEOTにNAKを返すので、ACKが返るまで送り直す
+        self.send_packet(&[EOT])
+    }
+
+    fn send(
+        &mut self,
+        protocol: ModemProtocol,
+        name: &str,
+        data: &[u8],
+        progress: &mut impl FnMut(u64),
+    ) -> Result<(), ModemError> {
+        match protocol {
+            ModemProtocol::XmodemCrc => {
+                self.wait_for_start()?;
+                self.send_data(data, 128, progress)
+            }
+            ModemProtocol::Xmodem1k => {
+                self.wait_for_start()?;
+                self.send_data(data, 1024, progress)
+            }
+            ModemProtocol::Ymodem => {
+                // 受信側を待たせる前に、ブロック0を作れるか確かめる
+                let header = header_packet(name, data.len())?;
+                self.wait_for_start()?;
+                // ブロック0はファイル名とサイズ。受け取った受信側は改めてCを送る
+                self.send_packet(&header)?;
+                self.wait_for_start()?;
+                self.send_data(data, 1024, progress)?;
+                // 空のブロック0でバッチを終える
+                self.wait_for_start()?;
+                self.send_packet(&packet(0, &[], 128, 0))
+            }
+        }
+    }
+}
+
+/// `protocol`で`data`を送る。`cancel`が真になったら終える。
+/// 途中で失敗したり中止したりしたら、受信側が待ち続けないようにCANを送って中止を伝える。
+pub fn send<P: Read + Write>(
+    port: &mut P,
+    protocol: ModemProtocol,
+    name: &str,
+    data: &[u8],
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(u64),
+) -> Result<(), ModemError> {
+    let result = Sender {
+        port: &mut *port,
+        cancel,
+    }
+    .send(protocol, name, data, &mut progress);
+    // 受信側が中止した場合と、受信側とやり取りを始める前の失敗では伝える必要がない
+    if let Err(e) = &result
+        && !matches!(e, ModemError::CancelledByReceiver | ModemError::NameTooLong)
+    {
+        // 失敗しても転送が失敗したことに変わりはないので、書き込みのエラーは無視する
+        let _ = port.write_all(&[CAN, CAN]).and_then(|()| port.flush());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crossbeam::channel::{self, Receiver, RecvTimeoutError};
+
+    use super::*;
+
+    /// チャネルでつないだ仮想のシリアルポート。
+    struct Loopback {
+        rx: Receiver<u8>,
+        tx: channel::Sender<u8>,
+    }
+
+    impl Loopback {
+        fn pair() -> (Self, Self) {
+            let (a_tx, a_rx) = channel::unbounded();
+            let (b_tx, b_rx) = channel::unbounded();
+            (Self { rx: a_rx, tx: b_tx }, Self { rx: b_rx, tx: a_tx })
+        }
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.rx.recv_timeout(Duration::from_millis(10)) {
+                Ok(byte) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::BrokenPipe.into()),
+            }
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for &byte in buf {
+                let _ = self.tx.send(byte);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// `count`バイト受信する。タイムアウトや途中までの読み込みは読み直す。
+    fn read_bytes(port: &mut impl Read, count: usize) -> Vec<u8> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut bytes = vec![0; count];
+        let mut filled = 0;
+        while filled < count {
+            assert!(Instant::now() < deadline, "the sender stopped");
+            match port.read(&mut bytes[filled..]) {
+                Ok(read) => filled += read,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) => {}
+                Err(e) => panic!("{e}"),
+            }
+        }
+        bytes
+    }
+
+    /// 受信側。パケットを検証してデータを返す。
+    /// 送り直しを試すため、最初のデータブロックとYMODEMの最初のEOTにはNAKを返す。
+    fn receive(port: &mut (impl Read + Write), ymodem: bool) -> (Vec<u8>, Option<String>) {
+        let mut data = Vec::new();
+        let mut header = None;
+        let mut block_rejected = false;
+        let mut eot_rejected = false;
+        port.write_all(&[CRC_REQUEST]).unwrap();
+        loop {
+            let size = match read_bytes(port, 1)[0] {
+                SOH => 128,
+                STX => 1024,
+                EOT if ymodem && !eot_rejected => {
+                    eot_rejected = true;
+                    port.write_all(&[NAK]).unwrap();
+                    continue;
+                }
+                EOT => {
+                    port.write_all(&[ACK]).unwrap();
+                    if ymodem {
+                        // 空のブロック0を受け取って終える
+                        port.write_all(&[CRC_REQUEST]).unwrap();
+                        let packet = read_bytes(port, 133);
+                        assert_eq!(packet[0], SOH);
+                        assert!(packet[3..131].iter().all(|&byte| byte == 0));
+                        port.write_all(&[ACK]).unwrap();
+                    }
+                    return (data, header);
+                }
+                other => panic!("unexpected byte {other:#04x}"),
+            };
+            let packet = read_bytes(port, size + 4);
+            assert_eq!(packet[0], !packet[1]);
+            let block = &packet[2..size + 2];
+            let crc = u16::from_be_bytes([packet[size + 2], packet[size + 3]]);
+            assert_eq!(crc, crc16(block));
+
+            if ymodem && header.is_none() {
+                assert_eq!(packet[0], 0);
+                header = Some(String::from_utf8_lossy(block).into_owned());
+                port.write_all(&[ACK, CRC_REQUEST]).unwrap();
+            } else if !block_rejected {
+                block_rejected = true;
+                port.write_all(&[NAK]).unwrap();
+            } else {
+                data.extend_from_slice(block);
+                port.write_all(&[ACK]).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    fn test_loopback_transfer() {
+        let data: Vec<u8> = (0..3000).map(|i| (i * 7) as u8).collect();
+        for protocol in [
+            ModemProtocol::XmodemCrc,
+            ModemProtocol::Xmodem1k,
+            ModemProtocol::Ymodem,
+        ] {
+            let (mut port, mut peer) = Loopback::pair();
+            let ymodem = protocol == ModemProtocol::Ymodem;
+            let receiver = thread::spawn(move || receive(&mut peer, ymodem));
+
+            let cancel = AtomicBool::new(false);
+            let mut progress = 0;
+            send(&mut port, protocol, "fw.bin", &data, &cancel, |sent| {
+                progress = sent
+            })
+            .unwrap();
+            assert_eq!(progress, data.len() as u64);
+
+            let (received, header) = receiver.join().unwrap();
+            // 最後のブロックは埋め草で埋まっている
+            assert_eq!(&received[..data.len()], data);
+            assert!(received[data.len()..].iter().all(|&byte| byte == PAD));
+            assert_eq!(
+                header.map(|header| header.trim_end_matches('\0').to_string()),
+                ymodem.then(|| "fw.bin\u{0}3000".to_string())
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pseudo_terminal_transfer() {
+        use serialport::{SerialPort, TTYPort};
+
+        let data: Vec<u8> = (0..5000).map(|i| (i * 13) as u8).collect();
+        // 128バイトのブロック0に収まらない名前
+        let name = "firmware-".repeat(20) + ".bin";
+        for protocol in ModemProtocol::ALL {
+            let (mut port, mut peer) = TTYPort::pair().unwrap();
+            port.set_timeout(Duration::from_millis(10)).unwrap();
+            peer.set_timeout(Duration::from_millis(10)).unwrap();
+            let ymodem = protocol == ModemProtocol::Ymodem;
+            // 相手側を閉じると最後の応答を読む前に切断されるので、送り終えるまで閉じない
+            let receiver = thread::spawn(move || (receive(&mut peer, ymodem), peer));
+
+            let cancel = AtomicBool::new(false);
+            send(&mut port, protocol, &name, &data, &cancel, |_| {}).unwrap();
+
+            let ((received, header), _peer) = receiver.join().unwrap();
+            assert_eq!(&received[..data.len()], data);
+            assert_eq!(
+                header.map(|header| header.trim_end_matches('\0').to_string()),
+                ymodem.then(|| format!("{name}\u{0}5000"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_name_too_long() {
+        let (mut port, peer) = Loopback::pair();
+        let cancel = AtomicBool::new(false);
+        let name = "x".repeat(1024);
+        let result = send(
+            &mut port,
+            ModemProtocol::Ymodem,
+            &name,
+            b"x",
+            &cancel,
+            |_| {},
+        );
+        assert!(matches!(result, Err(ModemError::NameTooLong)));
+        // 受信側を待たずに失敗する
+        assert!(peer.rx.try_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (mut port, peer) = Loopback::pair();
+        let cancel = AtomicBool::new(true);
+        let result = send(
+            &mut port,
+            ModemProtocol::XmodemCrc,
+            "",
+            b"x",
+            &cancel,
+            |_| {},
+        );
+        assert!(matches!(result, Err(ModemError::Cancelled)));
+        assert_eq!(peer.rx.try_iter().collect::<Vec<_>>(), [CAN, CAN]);
+    }
+
+    #[test]
+    fn test_failure_cancels_receiver() {
+        let (mut port, peer) = Loopback::pair();
+        // 開始の合図の後、ブロックを毎回拒否する
+        for byte in [CRC_REQUEST].into_iter().chain([NAK; MAX_RETRIES]) {
+            peer.tx.send(byte).unwrap();
+        }
+        let cancel = AtomicBool::new(false);
+        let result = send(
+            &mut port,
+            ModemProtocol::XmodemCrc,
+            "",
+            b"x",
+            &cancel,
+            |_| {},
+        );
+        assert!(matches!(result, Err(ModemError::TooManyRetries)));
+        // ブロックを送り直した後に中止を伝える
+        let received: Vec<u8> = peer.rx.try_iter().collect();
+        assert_eq!(received.len(), MAX_RETRIES * 133 + 2);
+        assert!(received.ends_with(&[CAN, CAN]));
+    }
+}
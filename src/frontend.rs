@@ -1,7 +1,38 @@
+mod byte_view;
+mod decimation;
+mod file_send;
+mod highlight;
+mod histogram;
+mod history_pager;
+mod macros;
+mod monitor;
+mod search;
+mod send_history;
+mod send_options;
+mod series_manager;
+mod settings;
+mod spectrum;
+mod statistics;
+mod trigger;
+
+use chrono::TimeDelta;
 use crossbeam::channel::Sender;
 use eframe::{App, egui};
 
-use crate::shared::{Event, SharedData, serial_read::SerialRead};
+use self::decimation::PlotCache;
+use self::file_send::FileSendDialog;
+use self::histogram::Histogram;
+use self::macros::MacroPanel;
+use self::monitor::Monitor;
+use self::send_history::{HistoryCursor, SentCommand};
+use self::send_options::LineEnding;
+use self::series_manager::SeriesManager;
+use self::settings::Settings;
+use self::spectrum::Spectrum;
+use self::statistics::StatsCache;
+use self::trigger::{Trigger, TriggerEvent};
+use crate::shared::serial_read::{RetentionPolicy, SerialRead};
+use crate::shared::{Event, SharedData};
 
 const BUTTON_WIDTH: f32 = 70.0;
 const BUTTON_HEIGHT: f32 = 20.0;
@@ -9,6 +40,17 @@ const DEFAULT_PLOT_RANGE: usize = 1000;
 const REPAINT_AFTER_MILLIS: u64 = 1000;
 const SELECTED_BUTTON_COLOR: egui::Color32 = egui::Color32::from_rgb(20, 100, 180);
 
+// 系列ごとに事前に定義された色のリスト
+const SERIES_COLORS: [egui::Color32; 4] = [
+    egui::Color32::from_rgb(100, 200, 100),
+    egui::Color32::from_rgb(200, 100, 100),
+    egui::Color32::from_rgb(100, 100, 200),
+    egui::Color32::from_rgb(200, 150, 100),
+];
+
+// 入力エラーや失敗を示す色
+const ERROR_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 90, 90);
+
 pub struct Frontend {
     shared_data: SharedData,
     event_sender: Sender<Event>,
@@ -16,55 +58,157 @@ pub struct Frontend {
     port_menu_open: bool,
     baud_rate_menu_open: bool,
 
-    enter_max_data_points: EnterMaxDataPoints,
+    enter_retention: EnterRetention,
 
     text_sender: String,
+    /// 上下キーで送信履歴をたどっている位置
+    history_cursor: HistoryCursor,
 
     show_type: ShowType,
 
+    monitor: Monitor,
+
     plot_range: usize,
+
+    /// ホールド中に表示している窓の終端（行番号、排他的）。`None`ならライブ表示。
+    plot_hold: Option<usize>,
+
+    /// 間引いた描画用データのキャッシュ
+    plot_cache: PlotCache,
+    stats_cache: StatsCache,
+
+    trigger: Trigger,
+
+    show_statistics: bool,
+
+    spectrum: Spectrum,
+
+    histogram: Histogram,
+
+    series_manager: SeriesManager,
+
+    macro_panel: MacroPanel,
+
+    file_send_dialog: FileSendDialog,
+
+    settings: Settings,
+}
+
+/// 履歴の保持範囲を入力するときの単位
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RetentionUnit {
+    Lines,
+    Seconds,
+    Minutes,
+    Megabytes,
+}
+
+impl RetentionUnit {
+    const ALL: [RetentionUnit; 4] = [
+        RetentionUnit::Lines,
+        RetentionUnit::Seconds,
+        RetentionUnit::Minutes,
+        RetentionUnit::Megabytes,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            RetentionUnit::Lines => "lines",
+            RetentionUnit::Seconds => "s",
+            RetentionUnit::Minutes => "min",
+            RetentionUnit::Megabytes => "MB",
+        }
+    }
+
+    fn policy(&self, value: usize) -> RetentionPolicy {
+        match self {
+            RetentionUnit::Lines => RetentionPolicy::Lines(value),
+            RetentionUnit::Seconds => RetentionPolicy::Duration(TimeDelta::seconds(value as i64)),
+            RetentionUnit::Minutes => RetentionPolicy::Duration(TimeDelta::minutes(value as i64)),
+            RetentionUnit::Megabytes => RetentionPolicy::Megabytes(value),
+        }
+    }
+
+    /// 保持範囲を入力欄の単位と値に戻す
+    fn from_policy(policy: RetentionPolicy) -> (Self, usize) {
+        match policy {
+            RetentionPolicy::Lines(lines) => (RetentionUnit::Lines, lines),
+            RetentionPolicy::Duration(duration) => {
+                let seconds = duration.num_seconds().max(0) as usize;
+                if seconds >= 60 && seconds.is_multiple_of(60) {
+                    (RetentionUnit::Minutes, seconds / 60)
+                } else {
+                    (RetentionUnit::Seconds, seconds)
+                }
+            }
+            RetentionPolicy::Megabytes(megabytes) => (RetentionUnit::Megabytes, megabytes),
+        }
+    }
 }
 
-enum EnterMaxDataPoints {
-    Value(usize),
+enum EnterRetention {
+    Value(RetentionPolicy),
     Typing {
-        current_value: usize,
+        current_value: RetentionPolicy,
         string: String,
+        unit: RetentionUnit,
     },
 }
 
-impl EnterMaxDataPoints {
-    fn ui(&mut self, event_sender: &mut Sender<Event>, ui: &mut eframe::egui::Ui) {
+impl EnterRetention {
+    fn ui(
+        &mut self,
+        event_sender: &mut Sender<Event>,
+        serial_read: &SerialRead,
+        ui: &mut eframe::egui::Ui,
+    ) {
         match self {
-            EnterMaxDataPoints::Value(val) => {
-                let button = egui::Button::new(format!("Data holds:  {val}"));
+            EnterRetention::Value(val) => {
+                let button = egui::Button::new(format!("Data holds:  {}", val.label()));
                 let button = ui.add_sized(eframe::egui::vec2(BUTTON_WIDTH * 2.0, BUTTON_HEIGHT), button);
+                let button = button.on_hover_text(format!(
+                    "{} lines, {:.1} MB retained",
+                    serial_read.timestamps.len(),
+                    serial_read.retained_bytes as f64 / 1_000_000.0
+                ));
                 if button.clicked() {
-                    *self = EnterMaxDataPoints::Typing {
+                    let (unit, value) = RetentionUnit::from_policy(*val);
+                    *self = EnterRetention::Typing {
                         current_value: *val,
-                        string: val.to_string(),
+                        string: value.to_string(),
+                        unit,
                     };
                 }
             }
-            EnterMaxDataPoints::Typing {
+            EnterRetention::Typing {
                 current_value,
                 string,
+                unit,
             } => {
                 let text_edit = ui.add_sized(
-                    eframe::egui::vec2(BUTTON_WIDTH * 2.0, BUTTON_HEIGHT),
+                    eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
                     egui::TextEdit::singleline(string)
-                        .hint_text("Enter max data points")
+                        .hint_text("Enter data holds")
                         .desired_width(BUTTON_WIDTH - 20.0),
                 );
+                egui::ComboBox::from_id_salt("retention_unit")
+                    .width(BUTTON_WIDTH - 20.0)
+                    .selected_text(unit.label())
+                    .show_ui(ui, |ui| {
+                        for candidate in RetentionUnit::ALL {
+                            ui.selectable_value(unit, candidate, candidate.label());
+                        }
+                    });
 
                 if text_edit.lost_focus() && ui.input(|i| i.key_pressed(eframe::egui::Key::Enter)) {
                     if let Ok(new_value) = string.parse::<usize>() {
+                        let retention = unit.policy(new_value);
                         event_sender
-                            .send(Event::ChangeMaxDataPoints(new_value))
-                            .expect("Failed to send ChangeMaxDataPoints event");
-                        *self = EnterMaxDataPoints::Value(new_value);
+                            .send(Event::ChangeRetention(retention))
+                            .expect("Failed to send ChangeRetention event");
+                        *self = EnterRetention::Value(retention);
                     } else {
-                        *self = EnterMaxDataPoints::Value(*current_value);
+                        *self = EnterRetention::Value(*current_value);
                     }
                 }
             }
@@ -76,27 +220,70 @@ impl EnterMaxDataPoints {
 enum ShowType {
     SerialMonitor,
     SerialPlotter,
+    Spectrum,
+    Histogram,
+}
+
+impl ShowType {
+    /// メニューに並べる順番
+    const ALL: [ShowType; 4] = [
+        ShowType::SerialMonitor,
+        ShowType::SerialPlotter,
+        ShowType::Spectrum,
+        ShowType::Histogram,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ShowType::SerialMonitor => "Monitor",
+            ShowType::SerialPlotter => "Plotter",
+            ShowType::Spectrum => "Spectrum",
+            ShowType::Histogram => "Histogram",
+        }
+    }
 }
 
 impl Frontend {
     pub fn new(shared_data: SharedData, event_sender: Sender<Event>) -> Self {
-        let enter_max_data_points =
-            EnterMaxDataPoints::Value(shared_data.read_data.read().max_data_points);
+        let enter_retention = EnterRetention::Value(shared_data.read_data.read().retention);
+        let settings = Settings::load().unwrap_or_else(|e| {
+            eprintln!("Failed to load settings: {e}");
+            *shared_data.error_log.lock() = format!("Failed to load settings: {e}");
+            Settings::default()
+        });
+        let mut macro_panel = MacroPanel::new();
+        if let Err(e) = macro_panel.load(&settings) {
+            eprintln!("Failed to load macros: {e}");
+            *shared_data.error_log.lock() = format!("Failed to load macros: {e}");
+        }
         Self {
             shared_data,
             event_sender,
             port_menu_open: false,
             baud_rate_menu_open: false,
-            enter_max_data_points,
+            enter_retention,
             text_sender: String::new(),
+            history_cursor: HistoryCursor::default(),
             show_type: ShowType::SerialMonitor,
+            monitor: Monitor::new(),
             plot_range: DEFAULT_PLOT_RANGE,
+            plot_hold: None,
+            plot_cache: PlotCache::new(),
+            stats_cache: StatsCache::new(),
+            trigger: Trigger::new(),
+            show_statistics: false,
+            spectrum: Spectrum::new(),
+            histogram: Histogram::new(),
+            series_manager: SeriesManager::new(),
+            macro_panel,
+            file_send_dialog: FileSendDialog::new(),
+            settings,
         }
     }
 }
 
 impl App for Frontend {
-    fn update(&mut self, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
         egui::containers::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.add_space(5.0);
             self.menu(ui);
@@ -106,6 +293,7 @@ impl App for Frontend {
         egui::containers::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
             ui.add_space(5.0);
             self.text_sender(ui);
+            file_send::transfer_status(&self.shared_data, &self.event_sender, ui);
             if !self.shared_data.error_log.lock().is_empty() {
                 ui.add_space(5.0);
                 ui.label(self.shared_data.error_log.lock().clone());
@@ -113,11 +301,40 @@ impl App for Frontend {
             ui.add_space(2.0);
         });
 
+        if self.macro_panel.open {
+            self.macro_panel.show(
+                ctx,
+                &self.shared_data,
+                &mut self.settings,
+                &self.event_sender,
+            );
+        }
+        if self.file_send_dialog.open {
+            self.file_send_dialog
+                .show(ctx, &self.shared_data, &self.settings, &self.event_sender);
+        }
+        self.macro_panel.check_shortcuts(
+            ctx,
+            &self.settings,
+            &self.shared_data,
+            &self.event_sender,
+        );
+
         egui::containers::CentralPanel::default().show(ctx, |ui| match self.show_type {
-            ShowType::SerialMonitor => self.monitor(ui),
+            ShowType::SerialMonitor => self.monitor.show(&self.shared_data, &mut self.settings, ui),
             ShowType::SerialPlotter => self.plotter(ui),
+            ShowType::Spectrum => self.spectrum.show(&self.shared_data.read_data.read(), ui),
+            ShowType::Histogram => self.histogram.show(&self.shared_data.read_data.read(), ui),
         });
 
+        self.monitor
+            .check_alerts(&self.shared_data.read_data.read(), &self.settings, ctx);
+
+        if self.series_manager.open {
+            self.series_manager
+                .show(ctx, &self.shared_data.read_data.read(), &self.event_sender);
+        }
+
         ctx.request_repaint_after(std::time::Duration::from_millis(REPAINT_AFTER_MILLIS));
     }
 }
@@ -202,7 +419,29 @@ impl Frontend {
 
                     ui.separator();
 
-                    self.enter_max_data_points.ui(&mut self.event_sender, ui);
+                    self.enter_retention.ui(
+                        &mut self.event_sender,
+                        &self.shared_data.read_data.read(),
+                        ui,
+                    );
+
+                    let disk_history = self.shared_data.read_data.read().disk_history.is_some();
+                    let mut disk_button = egui::Button::new("Disk");
+                    if disk_history {
+                        disk_button = disk_button.fill(SELECTED_BUTTON_COLOR);
+                    }
+                    let disk_button = ui
+                        .add_sized(eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT), disk_button)
+                        .on_hover_text(
+                            "Keep lines pushed out of memory in a temporary file. \
+                             The file is deleted when the history is turned off or the app exits, \
+                             so it does not survive a restart",
+                        );
+                    if disk_button.clicked() {
+                        self.event_sender
+                            .send(Event::SetDiskHistory(!disk_history))
+                            .expect("Failed to send SetDiskHistory event");
+                    }
 
                     let clear_log_button = ui.add_sized(
                         eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
@@ -213,34 +452,47 @@ impl Frontend {
                             .send(Event::ClearLog)
                             .expect("Failed to send ClearLog event");
                     }
-                },
-            );
 
-            ui.with_layout(
-                eframe::egui::Layout::right_to_left(eframe::egui::Align::Center),
-                |ui| {
-                    let mut plotter_button = egui::Button::new("Plotter");
-                    if self.show_type == ShowType::SerialPlotter {
-                        plotter_button = plotter_button.fill(SELECTED_BUTTON_COLOR);
+                    let mut series_button = egui::Button::new("Series");
+                    if self.series_manager.open {
+                        series_button = series_button.fill(SELECTED_BUTTON_COLOR);
                     }
-                    let plotter_button = ui.add_sized(
+                    let series_button = ui.add_sized(
                         eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
-                        plotter_button,
+                        series_button,
                     );
-                    if plotter_button.clicked() {
-                        self.show_type = ShowType::SerialPlotter;
+                    if series_button.clicked() {
+                        self.series_manager.open = !self.series_manager.open;
                     }
 
-                    let mut monitor_button = egui::Button::new("Monitor");
-                    if self.show_type == ShowType::SerialMonitor {
-                        monitor_button = monitor_button.fill(SELECTED_BUTTON_COLOR);
+                    let mut macros_button = egui::Button::new("Macros");
+                    if self.macro_panel.open {
+                        macros_button = macros_button.fill(SELECTED_BUTTON_COLOR);
                     }
-                    let monitor_button = ui.add_sized(
+                    let macros_button = ui.add_sized(
                         eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
-                        monitor_button,
+                        macros_button,
                     );
-                    if monitor_button.clicked() {
-                        self.show_type = ShowType::SerialMonitor;
+                    if macros_button.clicked() {
+                        self.macro_panel.open = !self.macro_panel.open;
+                    }
+                },
+            );
+
+            ui.with_layout(
+                eframe::egui::Layout::right_to_left(eframe::egui::Align::Center),
+                |ui| {
+                    // 右から順に配置されるので逆順に追加する
+                    for show_type in ShowType::ALL.into_iter().rev() {
+                        let mut button = egui::Button::new(show_type.label());
+                        if self.show_type == show_type {
+                            button = button.fill(SELECTED_BUTTON_COLOR);
+                        }
+                        let button =
+                            ui.add_sized(eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT), button);
+                        if button.clicked() {
+                            self.show_type = show_type;
+                        }
                     }
                 },
             );
@@ -248,90 +500,354 @@ impl Frontend {
     }
 
     fn text_sender(&mut self, ui: &mut eframe::egui::Ui) {
+        let mut encoded = Ok(Vec::new());
         ui.horizontal(|ui| {
             ui.label("Send:");
 
+            if ui
+                .toggle_value(&mut self.settings.send_options.hex, "Hex")
+                .on_hover_text("Send hex bytes like 01 A0 FF or 0x01,0xA0")
+                .changed()
+            {
+                self.settings.save_or_log(&self.shared_data);
+            }
+
             let size = ui.available_size()[0];
 
-            let text_edit_width = size - BUTTON_WIDTH - 10.0;
+            let text_edit_width = size - BUTTON_WIDTH * 2.0 - 90.0;
+
+            // 入力欄にフォーカスがあれば、上下キーで送信履歴をたどる
+            let port = self.shared_data.port_info.read().selected_port.clone();
+            let text_edit_id = ui.make_persistent_id("send_text");
+            let mut recalled = false;
+            if ui.memory(|memory| memory.has_focus(text_edit_id)) {
+                for (key, older) in [(egui::Key::ArrowUp, true), (egui::Key::ArrowDown, false)] {
+                    if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, key)) {
+                        let hex = self.settings.send_options.hex;
+                        recalled |= self.history_cursor.step(
+                            self.settings.send_history.commands(port.as_deref()),
+                            &mut self.text_sender,
+                            &mut self.settings.send_options.hex,
+                            older,
+                        );
+                        if self.settings.send_options.hex != hex {
+                            self.settings.save_or_log(&self.shared_data);
+                        }
+                    }
+                }
+            }
 
-            ui.add_sized(
+            let options = self.settings.send_options;
+            encoded = options.encode(&self.text_sender);
+            let mut text_edit = egui::TextEdit::singleline(&mut self.text_sender).id(text_edit_id);
+            if options.hex {
+                text_edit = text_edit
+                    .font(egui::TextStyle::Monospace)
+                    .hint_text("01 A0 FF");
+            }
+            if encoded.is_err() {
+                text_edit = text_edit.text_color(ERROR_COLOR);
+            }
+            let response = ui.add_sized(
                 eframe::egui::vec2(text_edit_width, BUTTON_HEIGHT),
-                egui::TextEdit::singleline(&mut self.text_sender),
+                text_edit,
             );
+            if response.changed() {
+                self.history_cursor.reset();
+            }
+            if recalled {
+                // 呼び出した履歴の末尾にカーソルを移す
+                if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), text_edit_id) {
+                    let end = egui::text::CCursor::new(self.text_sender.chars().count());
+                    state
+                        .cursor
+                        .set_char_range(Some(egui::text::CCursorRange::one(end)));
+                    state.store(ui.ctx(), text_edit_id);
+                }
+            }
+            let enter_pressed = options.enter_to_send
+                && response.lost_focus()
+                && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if let Err(e) = &encoded {
+                response.on_hover_text(e);
+            } else if enter_pressed {
+                response.request_focus();
+            }
+
+            self.send_history_menu(port.as_deref(), ui);
 
             let send_button = ui.add_sized(
                 eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
                 egui::Button::new("Send"),
             );
 
-            if send_button.clicked() && !self.text_sender.is_empty() {
-                self.event_sender
-                    .send(Event::SendText(self.text_sender.clone()))
-                    .expect("Failed to send SendText event");
-                self.text_sender.clear();
+            if self.send_options_menu(ui) {
+                self.settings.save_or_log(&self.shared_data);
+            }
+
+            if ui
+                .selectable_label(self.file_send_dialog.open, "File")
+                .on_hover_text("Send a file line by line or in chunks")
+                .clicked()
+            {
+                self.file_send_dialog.open = !self.file_send_dialog.open;
+            }
+
+            if (send_button.clicked() || enter_pressed) && !self.text_sender.is_empty() {
+                match &encoded {
+                    Ok(bytes) if bytes.is_empty() => {}
+                    Ok(bytes) => {
+                        // 16進数で送ったバイト列は、モニタで確認できるよう常に表示する
+                        self.event_sender
+                            .send(Event::SendBytes {
+                                bytes: bytes.clone(),
+                                echo: options.echo || options.hex,
+                            })
+                            .expect("Failed to send SendBytes event");
+                        self.settings.send_history.push(
+                            port.as_deref(),
+                            SentCommand::new(&self.text_sender, options.hex),
+                        );
+                        self.settings.save_or_log(&self.shared_data);
+                        self.history_cursor.reset();
+                        self.text_sender.clear();
+                    }
+                    Err(e) => {
+                        *self.shared_data.error_log.lock() = format!("Failed to send text: {e}");
+                    }
+                }
             }
         });
+        if let Err(e) = encoded {
+            ui.colored_label(ERROR_COLOR, e);
+        }
     }
 
-    fn monitor(&self, ui: &mut eframe::egui::Ui) {
-        let read_data = self.shared_data.read_data.read();
+    /// 最近送信した入力のメニュー。選ぶと入力欄に入れ、16進数の設定も送ったときに戻す。
+    fn send_history_menu(&mut self, port: Option<&str>, ui: &mut eframe::egui::Ui) {
+        let history_before = self.settings.send_history.clone();
+        let hex_before = self.settings.send_options.hex;
+        let history = &mut self.settings.send_history;
+        ui.menu_button("🕘", |ui| {
+            let commands = history.commands(port);
+            if commands.is_empty() {
+                ui.weak("No commands sent yet");
+            }
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for command in commands {
+                        let label = if command.hex {
+                            format!("[Hex] {}", command.text)
+                        } else {
+                            command.text.clone()
+                        };
+                        if ui.button(egui::RichText::new(label).monospace()).clicked() {
+                            self.text_sender = command.text.clone();
+                            self.settings.send_options.hex = command.hex;
+                            self.history_cursor.reset();
+                            ui.close();
+                        }
+                    }
+                });
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Keep");
+                if ui
+                    .add(egui::DragValue::new(&mut history.max_len).range(1..=1000))
+                    .changed()
+                {
+                    history.truncate();
+                }
+                ui.label("commands");
+            });
+            ui.checkbox(&mut history.per_port, "Separate history per port");
+            if ui.button("Clear history").clicked() {
+                history.clear(port);
+            }
+        })
+        .response
+        .on_hover_text("Recent commands (Up/Down in the input to recall)");
+        if self.settings.send_history != history_before
+            || self.settings.send_options.hex != hex_before
+        {
+            self.settings.save_or_log(&self.shared_data);
+        }
+    }
 
-        egui::ScrollArea::vertical()
-            .scroll([true, true])
-            // .stick_to_bottom(true) // 新しい要素追加時に一番下に追従
-            .show(ui, |ui| {
-                for line in read_data.raw_data.iter().rev() {
-                    ui.label(line.to_string());
+    /// 送信設定のメニューを表示し、設定を変更したら`true`を返す。
+    fn send_options_menu(&mut self, ui: &mut eframe::egui::Ui) -> bool {
+        let options = &mut self.settings.send_options;
+        let before = *options;
+        let label = match options.line_ending {
+            LineEnding::None => "None",
+            line_ending => line_ending.label(),
+        };
+        ui.menu_button(format!("{label} ⏷"), |ui| {
+            // 16進数のバイト列はそのまま送る
+            ui.add_enabled_ui(!options.hex, |ui| {
+                for line_ending in LineEnding::ALL {
+                    ui.radio_value(&mut options.line_ending, line_ending, line_ending.label());
                 }
+                ui.separator();
+                ui.checkbox(&mut options.interpret_escapes, "Interpret escapes")
+                    .on_hover_text("\\n \\r \\t \\0 \\\\ and \\xHH");
             });
+            ui.checkbox(&mut options.enter_to_send, "Send with Enter");
+            ui.checkbox(&mut options.echo, "Echo sent data")
+                .on_hover_text("Show sent data as TX lines in the monitor");
+        })
+        .response
+        .on_hover_text("Send options");
+        *options != before
     }
 
     fn plotter(&mut self, ui: &mut eframe::egui::Ui) {
         ui.horizontal(|ui| {
-            ui.with_layout(
-                eframe::egui::Layout::centered_and_justified(eframe::egui::Direction::LeftToRight),
-                |ui| {
-                    let range_input = ui.add_sized(
-                        eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
-                        egui::DragValue::new(&mut self.plot_range)
-                            .speed(1.0)
-                            .prefix("Plot Range: "),
-                    );
-                    if range_input.changed() {
-                        self.plot_range = self.plot_range.max(1);
-                    }
-                },
+            let range_input = ui.add_sized(
+                eframe::egui::vec2(BUTTON_WIDTH * 2.0, BUTTON_HEIGHT),
+                egui::DragValue::new(&mut self.plot_range)
+                    .speed(1.0)
+                    .prefix("Plot Range: "),
+            );
+            if range_input.changed() {
+                self.plot_range = self.plot_range.max(1);
+            }
+
+            ui.separator();
+
+            let mut hold_button = egui::Button::new("Hold");
+            if self.plot_hold.is_some() {
+                hold_button = hold_button.fill(SELECTED_BUTTON_COLOR);
+            }
+            let hold_button =
+                ui.add_sized(eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT), hold_button);
+            if hold_button.clicked() {
+                self.plot_hold = match self.plot_hold {
+                    Some(_) => None,
+                    None => Some(self.shared_data.read_data.read().line_counter),
+                };
+            }
+
+            let live_button = ui.add_enabled(
+                self.plot_hold.is_some(),
+                egui::Button::new("Jump to live")
+                    .min_size(eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT)),
             );
+            if live_button.clicked() {
+                self.plot_hold = None;
+            }
+
+            ui.separator();
+
+            let mut trigger_button = egui::Button::new("Trigger");
+            if self.trigger.enabled {
+                trigger_button = trigger_button.fill(SELECTED_BUTTON_COLOR);
+            }
+            let trigger_button = ui.add_sized(
+                eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
+                trigger_button,
+            );
+            if trigger_button.clicked() {
+                self.trigger.enabled = !self.trigger.enabled;
+                let line_counter = self.shared_data.read_data.read().line_counter;
+                self.trigger.arm(line_counter);
+            }
+
+            let mut stats_button = egui::Button::new("Stats");
+            if self.show_statistics {
+                stats_button = stats_button.fill(SELECTED_BUTTON_COLOR);
+            }
+            let stats_button = ui.add_sized(
+                eframe::egui::vec2(BUTTON_WIDTH, BUTTON_HEIGHT),
+                stats_button,
+            );
+            if stats_button.clicked() {
+                self.show_statistics = !self.show_statistics;
+            }
         });
 
+        let serial_read = self.shared_data.read_data.read();
+
+        if self.trigger.enabled {
+            self.trigger.ui(&serial_read, ui);
+        }
+
         ui.add_space(5.0);
 
-        Self::graph(&self.shared_data.read_data.read(), self.plot_range, ui);
+        let trigger = self.trigger.update(&serial_read, self.plot_range);
+        if let Some(event) = trigger.event.filter(|_| trigger.captured) {
+            // Singleモードで捕捉した窓はホールドして固定する
+            self.plot_hold = Some(self.trigger.view_end(event, self.plot_range));
+        }
+
+        // 保持されている履歴（ディスク上のものを含む）の中で選択できる窓の終端の範囲
+        let retained = serial_read.line_counter - serial_read.oldest_line();
+        let newest_end = serial_read.line_counter;
+        let oldest_end = newest_end - retained + self.plot_range.min(retained);
+
+        // ホールド中の窓が履歴から押し出された場合は、保持されている最古の位置に留める
+        if let Some(end) = self.plot_hold.as_mut() {
+            *end = (*end).clamp(oldest_end, newest_end);
+        }
+
+        let live_end = trigger.event.map_or(newest_end, |event| {
+            self.trigger.view_end(event, self.plot_range)
+        });
+
+        ui.horizontal(|ui| {
+            let mut view_end = self.plot_hold.unwrap_or(live_end);
+            ui.spacing_mut().slider_width = ui.available_width();
+            let scrollbar = ui.add_enabled(
+                oldest_end < newest_end,
+                egui::Slider::new(&mut view_end, oldest_end..=newest_end).show_value(false),
+            );
+            if scrollbar.changed() {
+                // スクロールバーを動かしたらホールドに入る。最新位置まで戻したらライブに復帰する
+                self.plot_hold = (view_end < newest_end).then_some(view_end);
+            }
+        });
+
+        ui.add_space(5.0);
+
+        let view_end = self.plot_hold.unwrap_or(live_end);
+
+        if self.show_statistics {
+            egui::SidePanel::right("statistics_panel")
+                .resizable(true)
+                .default_width(400.0)
+                .show_inside(ui, |ui| {
+                    statistics::statistics_panel(
+                        &mut self.stats_cache,
+                        &serial_read,
+                        view_end,
+                        self.plot_range,
+                        ui,
+                    );
+                });
+        }
+
+        // 横1ピクセルあたり1区間に間引く。新しいデータが届くまではキャッシュを使い回す
+        let buckets = ui.available_width().max(1.0) as usize;
+        if let Err(e) = self
+            .plot_cache
+            .update(&serial_read, view_end, self.plot_range, buckets)
+        {
+            eprintln!("Failed to read history file: {e}");
+            *self.shared_data.error_log.lock() = format!("Failed to read history file: {e}");
+        }
+
+        Self::graph(&serial_read, &self.plot_cache, trigger.event, ui);
     }
 
-    fn graph(serial_read: &SerialRead, plot_range: usize, ui: &mut eframe::egui::Ui) {
-        // --- ステージ1 & 2: データ抽出、サニタイズ、座標マッピング ---
-        let data_guard = &serial_read.graph_data;
-
-        // 各データ系列を処理し、プロット可能な座標のベクタに変換する。
-        // この処理はイテレータチェーンを駆使して効率的に行われる。
-        let processed_series: Vec<Vec<[f64; 2]>> = data_guard
-            .iter()
-            .map(|each_data_series| {
-                each_data_series
-                    .iter()
-                    .take(plot_range) // 指定された範囲のデータポイントを抽出
-                    // 先頭が最新
-                    .enumerate() // X軸のインデックスを付与
-                    .filter_map(|(index, &value)| {
-                        Some([(serial_read.line_counter - index - 1) as f64, value?])
-                    }) // [x, y]形式のPlotPointに変換
-                    .rev()
-                    // 末尾が最新
-                    .collect()
-            })
-            .collect();
+    /// `plot_cache`に間引いておいた窓を描画する。
+    fn graph(
+        serial_read: &SerialRead,
+        plot_cache: &PlotCache,
+        trigger: Option<TriggerEvent>,
+        ui: &mut eframe::egui::Ui,
+    ) {
+        // --- ステージ1 & 2: データ抽出と間引きはplot_cacheで済ませてある ---
 
         // --- ステージ3: 動的なY軸境界の事前計算 ---
         let mut plot = egui_plot::Plot::new("serial plot")
@@ -339,58 +855,46 @@ impl Frontend {
             .y_axis_label("Value")
             .legend(egui_plot::Legend::default());
 
-        // 描画対象の全ポイントをフラットなリストに集める
-        let all_points: Vec<[f64; 2]> = processed_series
-            .iter()
-            .flat_map(|series_points| series_points.iter())
-            .cloned()
-            .collect();
+        match plot_cache.y_bounds {
+            Some((min_y, max_y)) => {
+                // グラフが見やすくなるように、上下に5%のマージンを追加する
+                let margin = (max_y - min_y) * 0.05;
+                // マージンが0（全データが同じ値）の場合のフォールバック
+                let final_margin = if margin > 0.0 { margin } else { 1.0 };
 
-        if all_points.is_empty() {
+                plot = plot
+                    .include_y(min_y - final_margin)
+                    .include_y(max_y + final_margin);
+            }
             // 表示するデータがない場合は、デフォルトの表示範囲を設定する
-            plot = plot.include_y(0.0).include_y(1.0);
-        } else {
-            // f64はOrdを実装していないため、foldを使用して最小/最大値を見つける
-            let min_y = all_points
-                .iter()
-                .map(|p| p[1]) // Noneを除外
-                .fold(f64::INFINITY, |min, p| min.min(p));
-            let max_y = all_points
-                .iter()
-                .map(|p| p[1])
-                .fold(f64::NEG_INFINITY, |max, p| max.max(p));
-
-            // グラフが見やすくなるように、上下に5%のマージンを追加する
-            let margin = (max_y - min_y) * 0.05;
-            // マージンが0（全データが同じ値）の場合のフォールバック
-            let final_margin = if margin > 0.0 { margin } else { 1.0 };
-
-            plot = plot
-                .include_y(min_y - final_margin)
-                .include_y(max_y + final_margin);
+            None => plot = plot.include_y(0.0).include_y(1.0),
         }
 
         // --- ステージ4: プロットのレンダリング ---
         plot.show(ui, |plot_ui| {
-            // 事前に定義された色のリスト
-            let colors = [
-                egui::Color32::from_rgb(100, 200, 100),
-                egui::Color32::from_rgb(200, 100, 100),
-                egui::Color32::from_rgb(100, 100, 200),
-                egui::Color32::from_rgb(200, 150, 100),
-            ];
-
-            for (i, series_points) in processed_series.iter().enumerate() {
+            for (i, series_points) in plot_cache.series.iter().enumerate() {
                 if !series_points.is_empty() {
-                    let line = egui_plot::Line::new(
-                        i.to_string(),
-                        egui_plot::PlotPoints::new(series_points.clone()),
-                    )
-                    .name(format!("Series {}", i + 1))
-                    .color(colors[i % colors.len()]);
+                    let line = egui_plot::Line::new(i.to_string(), series_points.as_slice())
+                        .name(serial_read.series_name(i))
+                        .color(SERIES_COLORS[i % SERIES_COLORS.len()]);
                     plot_ui.line(line);
                 }
             }
+
+            // トリガのレベルと位置を表示する
+            if let Some(event) = trigger {
+                let color = egui::Color32::from_rgb(220, 200, 60);
+                plot_ui.hline(
+                    egui_plot::HLine::new("trigger level", event.level)
+                        .color(color)
+                        .style(egui_plot::LineStyle::dashed_dense()),
+                );
+                plot_ui.vline(
+                    egui_plot::VLine::new("trigger point", event.line as f64)
+                        .color(color)
+                        .style(egui_plot::LineStyle::dashed_dense()),
+                );
+            }
         });
     }
 }
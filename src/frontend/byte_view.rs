@@ -0,0 +1,117 @@
+// src/frontend/byte_view.rs
+
+use std::fmt::Write;
+
+/// 16進ダンプの1段に並べるバイト数
+const BYTES_PER_ROW: usize = 16;
+
+/// 1行分のバイト列を16バイトずつの段に分け、「位置  16進数  |ASCII|」の形式で表示する。
+/// 段は改行でつなぐ。表示できないバイトはASCIIの欄では`.`になる。
+pub fn hex_dump(offset: u64, bytes: &[u8]) -> String {
+    // 何も届いていない受信中の行も、位置だけは1段表示する
+    let rows: Vec<&[u8]> = if bytes.is_empty() {
+        vec![bytes]
+    } else {
+        bytes.chunks(BYTES_PER_ROW).collect()
+    };
+
+    let mut dump = String::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        if i > 0 {
+            dump.push('\n');
+        }
+        let _ = write!(dump, "{:08X}  ", offset + (i * BYTES_PER_ROW) as u64);
+        for byte in row {
+            let _ = write!(dump, "{byte:02X} ");
+        }
+        // 短い段もASCIIの欄が揃うように空白で埋める
+        dump.extend(std::iter::repeat_n("   ", BYTES_PER_ROW - row.len()));
+        dump.push_str(" |");
+        dump.extend(row.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        dump.push('|');
+    }
+    dump
+}
+
+/// バイト列を`01 A0 FF`の形式で表示する。
+pub fn hex_bytes(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 3);
+    for byte in bytes {
+        if !hex.is_empty() {
+            hex.push(' ');
+        }
+        let _ = write!(hex, "{byte:02X}");
+    }
+    hex
+}
+
+/// 表示できる文字はそのまま、制御文字とUTF-8として不正なバイトは`<0x1B>`の形式で表示する。
+/// タブは制御文字だがそのまま残す。
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() && c != '\t' {
+                // C1制御文字のような複数バイトの文字も、バイトごとに表示する
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    let _ = write!(escaped, "<0x{byte:02X}>");
+                }
+            } else {
+                escaped.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(escaped, "<0x{byte:02X}>");
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump() {
+        let padding = "   ".repeat(11);
+        assert_eq!(
+            hex_dump(0x1F, b"A\x1b\xff \n"),
+            format!("0000001F  41 1B FF 20 0A {padding} |A.. .|")
+        );
+        assert_eq!(
+            hex_dump(0, b""),
+            format!("00000000  {} ||", "   ".repeat(16))
+        );
+        assert_eq!(hex_bytes(b"\x01\xa0\xff"), "01 A0 FF");
+    }
+
+    #[test]
+    fn test_hex_dump_long_line() {
+        // 16バイトを超える行は段に分かれ、段ごとに位置が進む
+        let dump = hex_dump(0x10, b"0123456789abcdefXY\n");
+        let rows: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            rows,
+            [
+                "00000010  30 31 32 33 34 35 36 37 38 39 61 62 63 64 65 66  |0123456789abcdef|",
+                &format!("00000020  58 59 0A {} |XY.|", "   ".repeat(13)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escape_bytes() {
+        assert_eq!(
+            escape_bytes(b"\x1b[31mred\r\t\xff\xe3\x81\x82"),
+            "<0x1B>[31mred<0x0D>\t<0xFF>あ"
+        );
+        // C1制御文字 (U+0085)
+        assert_eq!(escape_bytes("a\u{85}".as_bytes()), "a<0xC2><0x85>");
+    }
+}
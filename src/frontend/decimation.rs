@@ -0,0 +1,190 @@
+// src/frontend/decimation.rs
+
+use std::io;
+
+use egui_plot::PlotPoint;
+
+use super::history_pager::HistoryPager;
+use crate::shared::history::HistoryLine;
+use crate::shared::serial_read::SerialRead;
+
+/// キャッシュを作り直す条件。どれか一つでも変わったら再計算する。
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CacheKey {
+    revision: u64,
+    view_end: usize,
+    plot_range: usize,
+    buckets: usize,
+}
+
+/// 間引いた描画用の点とY軸の範囲を、フレームをまたいで保持する。
+/// 新しいデータが届くか、表示する窓や幅が変わるまで再計算しない。
+pub struct PlotCache {
+    key: Option<CacheKey>,
+    /// 系列ごとの描画する点。X昇順
+    pub series: Vec<Vec<PlotPoint>>,
+    /// 全系列の点の最小値と最大値
+    pub y_bounds: Option<(f64, f64)>,
+    /// 窓がメモリに残っている範囲より古い場合に、ディスクから読み込んだ行
+    pager: HistoryPager,
+}
+
+impl PlotCache {
+    pub fn new() -> Self {
+        Self {
+            key: None,
+            series: Vec::new(),
+            y_bounds: None,
+            pager: HistoryPager::new(),
+        }
+    }
+
+    /// `view_end`（排他的な行番号）で終わる`plot_range`行分の窓を、`buckets`個の区間に間引く。
+    /// メモリから押し出された部分はディスク上の履歴から読み込む。
+    pub fn update(
+        &mut self,
+        serial_read: &SerialRead,
+        view_end: usize,
+        plot_range: usize,
+        buckets: usize,
+    ) -> io::Result<()> {
+        let key = CacheKey {
+            revision: serial_read.revision,
+            view_end,
+            plot_range,
+            buckets: buckets.max(1),
+        };
+        if self.key == Some(key) {
+            return Ok(());
+        }
+
+        let start = view_end.saturating_sub(plot_range);
+        let first_line = serial_read.first_line();
+        let page: &[HistoryLine] = match &serial_read.disk_history {
+            Some(history) if start < first_line => {
+                self.pager.page(history, start..view_end.min(first_line))?
+            }
+            _ => &[],
+        };
+
+        self.series = serial_read
+            .graph_data
+            .iter()
+            .enumerate()
+            .map(|(i, series)| {
+                // 系列の並びは書き出した後で変わることがあるので、ディスク上の値は識別子で探す
+                let id = serial_read.series_id(i);
+                let from_disk = page.iter().filter_map(|line| {
+                    let &(_, value) = line.values.iter().find(|&&(series, _)| series == id)?;
+                    Some([line.line as f64, value])
+                });
+                let from_memory = series
+                    .range(start..view_end)
+                    .map(|(line, value)| [line as f64, value]);
+                decimate(
+                    from_disk.chain(from_memory),
+                    start as f64,
+                    plot_range as f64,
+                    key.buckets,
+                )
+            })
+            .collect();
+
+        // 各区間の最小値と最大値を残しているので、間引いた点から求めても範囲は変わらない
+        self.y_bounds = self.series.iter().flatten().map(|point| point.y).fold(
+            None,
+            |bounds, y| match bounds {
+                None => Some((y, y)),
+                Some((min, max)) => Some((y.min(min), y.max(max))),
+            },
+        );
+
+        self.key = Some(key);
+        Ok(())
+    }
+}
+
+/// X昇順の`points`を、`x_start`から幅`x_span`の範囲を`buckets`等分した区間ごとにまとめ、
+/// 各区間の最小値と最大値の点だけを残す。
+/// 1区間に2点以下しかない場合は全ての点がそのまま残る。
+pub fn decimate(
+    points: impl Iterator<Item = [f64; 2]>,
+    x_start: f64,
+    x_span: f64,
+    buckets: usize,
+) -> Vec<PlotPoint> {
+    let mut decimated = Vec::with_capacity(buckets * 2);
+    // (区間番号, 最小値の点, 最大値の点)
+    let mut current: Option<(usize, [f64; 2], [f64; 2])> = None;
+
+    for point in points {
+        let position = (point[0] - x_start) / x_span.max(1.0) * buckets as f64;
+        let bucket = (position.max(0.0) as usize).min(buckets.saturating_sub(1));
+        match current.as_mut() {
+            Some((current_bucket, min, max)) if *current_bucket == bucket => {
+                if point[1] < min[1] {
+                    *min = point;
+                }
+                if point[1] > max[1] {
+                    *max = point;
+                }
+            }
+            _ => {
+                if let Some(finished) = current.replace((bucket, point, point)) {
+                    push_bucket(&mut decimated, finished);
+                }
+            }
+        }
+    }
+    if let Some(finished) = current {
+        push_bucket(&mut decimated, finished);
+    }
+
+    decimated
+}
+
+/// 区間の最小値と最大値の点を、X順を保って追加する。
+fn push_bucket(decimated: &mut Vec<PlotPoint>, (_, min, max): (usize, [f64; 2], [f64; 2])) {
+    if min == max {
+        decimated.push(min.into());
+    } else if min[0] < max[0] {
+        decimated.extend([PlotPoint::from(min), PlotPoint::from(max)]);
+    } else {
+        decimated.extend([PlotPoint::from(max), PlotPoint::from(min)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinates(points: &[PlotPoint]) -> Vec<[f64; 2]> {
+        points.iter().map(|point| [point.x, point.y]).collect()
+    }
+
+    #[test]
+    fn test_decimate() {
+        // 4区間 × 2点なら間引かれない
+        let points: Vec<[f64; 2]> = (0..8).map(|i| [i as f64, (i % 3) as f64]).collect();
+        assert_eq!(
+            coordinates(&decimate(points.iter().copied(), 0.0, 8.0, 4)),
+            points
+        );
+
+        // 2区間に4点ずつ。各区間の最小値と最大値がX順に残る
+        let points = [
+            [0.0, 1.0],
+            [1.0, 5.0],
+            [2.0, -3.0],
+            [3.0, 2.0],
+            [4.0, 0.0],
+            [5.0, 0.0],
+            [6.0, 0.0],
+            [7.0, 0.0],
+        ];
+        assert_eq!(
+            coordinates(&decimate(points.into_iter(), 0.0, 8.0, 2)),
+            vec![[1.0, 5.0], [2.0, -3.0], [4.0, 0.0]]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+// src/frontend/file_send.rs
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crossbeam::channel::Sender;
+use eframe::egui;
+use regex::Regex;
+
+use super::ERROR_COLOR;
+use super::send_options::LineEnding;
+use super::settings::Settings;
+use crate::shared::transfer::{
+    FileSend, FileSendUnit, ModemProtocol, ModemSend, TransferProgress, TransferState,
+};
+use crate::shared::{Event, SharedData};
+
+/// ファイルを送信する設定を入力するウィンドウ。
+pub struct FileSendDialog {
+    pub open: bool,
+    path: String,
+    /// `None`なら下の設定で少しずつ送り、それ以外はポートを占有してプロトコルで送る
+    protocol: Option<ModemProtocol>,
+    /// 偽なら1行ずつ、真なら`chunk_size`バイトずつ送る
+    chunked: bool,
+    line_ending: LineEnding,
+    chunk_size: usize,
+    delay_ms: u64,
+    /// 送信するたびに応答を待つ
+    wait: bool,
+    pattern: String,
+    timeout_secs: f64,
+}
+
+impl FileSendDialog {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            path: String::new(),
+            protocol: None,
+            chunked: false,
+            line_ending: LineEnding::Lf,
+            chunk_size: 64,
+            delay_ms: 0,
+            wait: false,
+            pattern: "^ok".to_string(),
+            timeout_secs: 5.0,
+        }
+    }
+
+    /// 入力中の設定を検証し、転送を始めるイベントを作る。
+    fn validate(&self, echo: bool) -> Result<Event, String> {
+        let path = self.path.trim();
+        if path.is_empty() {
+            return Err("Enter the path of the file to send".to_string());
+        }
+        if let Some(protocol) = self.protocol {
+            return Ok(Event::ModemSend(ModemSend {
+                path: PathBuf::from(path),
+                protocol,
+            }));
+        }
+        let wait_for = if self.wait {
+            Some(Regex::new(&self.pattern).map_err(|e| e.to_string())?)
+        } else {
+            None
+        };
+        Ok(Event::SendFile(FileSend {
+            path: PathBuf::from(path),
+            unit: if self.chunked {
+                FileSendUnit::Chunks(self.chunk_size)
+            } else {
+                FileSendUnit::Lines {
+                    line_ending: self.line_ending.bytes().to_vec(),
+                }
+            },
+            delay: Duration::from_millis(self.delay_ms),
+            wait_for,
+            timeout: Duration::from_secs_f64(self.timeout_secs),
+            echo,
+        }))
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        shared_data: &SharedData,
+        settings: &Settings,
+        event_sender: &Sender<Event>,
+    ) {
+        let running = shared_data
+            .transfer
+            .read()
+            .as_ref()
+            .is_some_and(|transfer| transfer.state == TransferState::Running);
+        let mut open = self.open;
+        egui::Window::new("Send file")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("file send options")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("File");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.path)
+                                .hint_text("path/to/file.gcode")
+                                .desired_width(300.0),
+                        );
+                        ui.end_row();
+
+                        ui.label("Protocol");
+                        egui::ComboBox::from_id_salt("file protocol")
+                            .selected_text(self.protocol.map_or("Paced", ModemProtocol::label))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.protocol, None, "Paced")
+                                    .on_hover_text(
+                                        "Send the file as is, line by line or in chunks",
+                                    );
+                                for protocol in ModemProtocol::ALL {
+                                    ui.selectable_value(
+                                        &mut self.protocol,
+                                        Some(protocol),
+                                        protocol.label(),
+                                    )
+                                    .on_hover_text(
+                                        "Take over the port until the receiver has the file",
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        if self.protocol.is_some() {
+                            return;
+                        }
+
+                        ui.label("Send");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.chunked, false, "Line by line");
+                            ui.radio_value(&mut self.chunked, true, "In chunks");
+                        });
+                        ui.end_row();
+
+                        if self.chunked {
+                            ui.label("Chunk size");
+                            ui.add(
+                                egui::DragValue::new(&mut self.chunk_size)
+                                    .range(1..=65536)
+                                    .suffix(" bytes"),
+                            );
+                        } else {
+                            ui.label("Line ending");
+                            egui::ComboBox::from_id_salt("file line ending")
+                                .selected_text(self.line_ending.label())
+                                .show_ui(ui, |ui| {
+                                    for line_ending in LineEnding::ALL {
+                                        ui.selectable_value(
+                                            &mut self.line_ending,
+                                            line_ending,
+                                            line_ending.label(),
+                                        );
+                                    }
+                                });
+                        }
+                        ui.end_row();
+
+                        ui.label("Delay");
+                        ui.add(
+                            egui::DragValue::new(&mut self.delay_ms)
+                                .range(0..=60_000)
+                                .suffix(" ms"),
+                        )
+                        .on_hover_text("Wait between lines or chunks");
+                        ui.end_row();
+
+                        ui.checkbox(&mut self.wait, "Wait for");
+                        ui.add_enabled_ui(self.wait, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut self.pattern)
+                                        .font(egui::TextStyle::Monospace)
+                                        .desired_width(120.0),
+                                )
+                                .on_hover_text(
+                                    "Regex a received line must match before the next send",
+                                );
+                                ui.label("timeout");
+                                ui.add(
+                                    egui::DragValue::new(&mut self.timeout_secs)
+                                        .range(0.1..=600.0)
+                                        .speed(0.1)
+                                        .suffix(" s"),
+                                );
+                            });
+                        });
+                        ui.end_row();
+                    });
+
+                ui.add_space(5.0);
+                let options = self.validate(settings.send_options.echo);
+                if let Err(e) = &options {
+                    ui.colored_label(ERROR_COLOR, e);
+                }
+                let start = ui.add_enabled(options.is_ok() && !running, egui::Button::new("Start"));
+                if start.clicked()
+                    && let Ok(event) = options
+                {
+                    event_sender
+                        .send(event)
+                        .expect("Failed to send file transfer event");
+                }
+            });
+        self.open = open;
+    }
+}
+
+/// 転送の進み具合を表示する。実行中なら中止、終わっていれば閉じるボタンを付ける。
+pub fn transfer_status(shared_data: &SharedData, event_sender: &Sender<Event>, ui: &mut egui::Ui) {
+    let Some(progress) = shared_data.transfer.read().clone() else {
+        return;
+    };
+    let TransferProgress {
+        name,
+        done,
+        total,
+        state,
+    } = &progress;
+
+    ui.horizontal(|ui| {
+        let text = match state {
+            TransferState::Running => format!("Sending {name}: {done} / {total} bytes"),
+            TransferState::Done => format!("Sent {name} ({total} bytes)"),
+            TransferState::Cancelled => format!("Cancelled {name} after {done} bytes"),
+            TransferState::Failed(e) => format!("Failed to send {name}: {e}"),
+        };
+        let bar = egui::ProgressBar::new(progress.fraction())
+            .desired_width(ui.available_width() - 80.0)
+            .text(text);
+        if matches!(state, TransferState::Failed(_)) {
+            ui.add(bar.fill(ERROR_COLOR));
+        } else {
+            ui.add(bar);
+        }
+
+        if *state == TransferState::Running {
+            // 進み具合が滑らかに見えるよう、実行中はこまめに描画し直す
+            ui.ctx().request_repaint_after(Duration::from_millis(100));
+            if ui.button("Cancel").clicked() {
+                event_sender
+                    .send(Event::CancelTransfer)
+                    .expect("Failed to send CancelTransfer event");
+            }
+        } else if ui.button("Close").clicked() {
+            *shared_data.transfer.write() = None;
+        }
+    });
+}
@@ -0,0 +1,307 @@
+// src/frontend/highlight.rs
+
+use chrono::{DateTime, Utc};
+use eframe::egui;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::ERROR_COLOR;
+use crate::shared::ansi;
+use crate::shared::serial_read::SerialRead;
+
+/// 正規表現に一致した行の色と警告の設定。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HighlightRule {
+    pub enabled: bool,
+    pub pattern: String,
+    /// 文字色と背景色（sRGB）。`None`なら変えない
+    pub foreground: Option<[u8; 3]>,
+    pub background: Option<[u8; 3]>,
+    pub bold: bool,
+    /// 一致した行が届いたら知らせる
+    pub alert: bool,
+    /// 知らせるときに音も鳴らす
+    #[serde(default)]
+    pub beep: bool,
+}
+
+impl HighlightRule {
+    fn new(pattern: &str, foreground: [u8; 3], bold: bool) -> Self {
+        Self {
+            enabled: true,
+            pattern: pattern.to_string(),
+            foreground: Some(foreground),
+            background: None,
+            bold,
+            alert: false,
+            beep: false,
+        }
+    }
+
+    /// よく使われるログレベルのルール。
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new(r"\b(ERROR|ERR|FATAL|PANIC)\b", [230, 90, 90], true),
+            Self::new(r"\bWARN(ING)?\b", [230, 180, 60], false),
+            Self::new(r"\b(OK|PASS(ED)?|SUCCESS)\b", [100, 200, 100], false),
+            Self::new(r"\b(DEBUG|TRACE)\b", [140, 140, 140], false),
+        ]
+    }
+
+    pub fn foreground(&self) -> Option<egui::Color32> {
+        self.foreground
+            .map(|[r, g, b]| egui::Color32::from_rgb(r, g, b))
+    }
+
+    pub fn background(&self) -> Option<egui::Color32> {
+        self.background
+            .map(|[r, g, b]| egui::Color32::from_rgb(r, g, b))
+    }
+}
+
+/// 警告するルールに一致した行。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alert {
+    /// 一致した最新の行の行番号
+    pub line: usize,
+    /// 一致したルールのどれかが音を鳴らす
+    pub beep: bool,
+}
+
+/// 強調表示のルールを正規表現にしたもの。届いた行を順に調べて警告を出す。
+pub struct Highlighter {
+    /// `patterns`を作ったときのルール
+    rules: Vec<HighlightRule>,
+    /// ルールごとの正規表現。無効なルールや不正な正規表現は`None`
+    patterns: Vec<Option<Regex>>,
+
+    /// 次に警告を調べる行の行番号
+    scanned_end: usize,
+    /// 調べたときの最初の行の受信時刻。クリアされて変わったら最初から調べる
+    session: Option<DateTime<Utc>>,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            patterns: Vec::new(),
+            scanned_end: 0,
+            session: None,
+        }
+    }
+
+    /// ルールが変わっていれば正規表現を作り直す。
+    pub fn sync(&mut self, rules: &[HighlightRule]) {
+        if self.rules == rules {
+            return;
+        }
+        self.rules = rules.to_vec();
+        self.patterns = rules
+            .iter()
+            .map(|rule| {
+                (rule.enabled && !rule.pattern.is_empty())
+                    .then(|| Regex::new(&rule.pattern).ok())
+                    .flatten()
+            })
+            .collect();
+    }
+
+    /// `text`に最初に一致したルール。
+    pub fn rule_for(&self, text: &str) -> Option<&HighlightRule> {
+        self.patterns
+            .iter()
+            .position(|pattern| pattern.as_ref().is_some_and(|p| p.is_match(text)))
+            .map(|index| &self.rules[index])
+    }
+
+    /// 前回から新しく確定した行を調べ、警告するルールに一致した行があれば返す。
+    pub fn scan(&mut self, read_data: &SerialRead) -> Option<Alert> {
+        if self.session != read_data.start_time || self.scanned_end > read_data.line_counter {
+            self.session = read_data.start_time;
+            self.scanned_end = 0;
+        }
+
+        let first_line = read_data.first_line();
+        let mut alert: Option<Alert> = None;
+        for line in self.scanned_end.max(first_line)..read_data.line_counter {
+            if let Some(rule) = self
+                .rule_for(&ansi::strip(&read_data.raw_data[line - first_line]))
+                .filter(|rule| rule.alert)
+            {
+                alert = Some(Alert {
+                    line,
+                    beep: rule.beep || alert.is_some_and(|alert| alert.beep),
+                });
+            }
+        }
+        self.scanned_end = read_data.line_counter;
+        alert
+    }
+}
+
+/// 強調表示のルールを編集するウィンドウ。
+pub struct HighlightEditor {
+    pub open: bool,
+    /// 保存していない変更がある
+    unsaved: bool,
+}
+
+impl HighlightEditor {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            unsaved: false,
+        }
+    }
+
+    fn color_option(color: &mut Option<[u8; 3]>, default: [u8; 3], ui: &mut egui::Ui) {
+        let mut enabled = color.is_some();
+        if ui.checkbox(&mut enabled, "").changed() {
+            *color = enabled.then_some(default);
+        }
+        match color {
+            Some(color) => {
+                ui.color_edit_button_srgb(color);
+            }
+            None => {
+                ui.label("-");
+            }
+        }
+    }
+
+    /// ウィンドウを表示し、変更を保存すべきときに`true`を返す。
+    /// 入力中に毎回保存しないよう、正規表現の入力欄からフォーカスが外れるか、
+    /// ドラッグを終えるか、ウィンドウを閉じるまで待つ。
+    pub fn show(&mut self, ctx: &egui::Context, rules: &mut Vec<HighlightRule>) -> bool {
+        let before = rules.clone();
+        let mut open = self.open;
+        let mut editing = false;
+        egui::Window::new("Highlight rules")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label("Lines are colored by the first enabled rule whose regex matches.");
+                ui.add_space(5.0);
+
+                let mut remove = None;
+                let mut move_up = None;
+                egui::Grid::new("highlight rules")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("On");
+                        ui.label("Regex");
+                        ui.label("Text");
+                        ui.label("");
+                        ui.label("Background");
+                        ui.label("");
+                        ui.label("Bold");
+                        ui.label("Alert");
+                        ui.label("Beep");
+                        ui.end_row();
+
+                        for (i, rule) in rules.iter_mut().enumerate() {
+                            ui.checkbox(&mut rule.enabled, "");
+                            let error = Regex::new(&rule.pattern).err();
+                            let mut pattern = egui::TextEdit::singleline(&mut rule.pattern)
+                                .font(egui::TextStyle::Monospace)
+                                .desired_width(200.0);
+                            if error.is_some() {
+                                pattern = pattern.text_color(ERROR_COLOR);
+                            }
+                            let response = ui.add(pattern);
+                            editing |= response.has_focus();
+                            if let Some(error) = error {
+                                response.on_hover_text(error.to_string());
+                            }
+                            Self::color_option(&mut rule.foreground, [255, 255, 255], ui);
+                            Self::color_option(&mut rule.background, [80, 80, 80], ui);
+                            ui.checkbox(&mut rule.bold, "");
+                            ui.checkbox(&mut rule.alert, "")
+                                .on_hover_text("Notify when a matching line arrives");
+                            ui.add_enabled(
+                                rule.alert,
+                                egui::Checkbox::without_text(&mut rule.beep),
+                            )
+                            .on_hover_text(
+                                "Also ring the terminal bell. Whether it sounds depends on the terminal the app was started from",
+                            );
+                            if i > 0 && ui.small_button("⬆").clicked() {
+                                move_up = Some(i);
+                            }
+                            if ui.small_button("Remove").clicked() {
+                                remove = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                if let Some(i) = move_up {
+                    rules.swap(i - 1, i);
+                }
+                if let Some(i) = remove {
+                    rules.remove(i);
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Add rule").clicked() {
+                        rules.push(HighlightRule::new("", [255, 255, 255], false));
+                    }
+                    if ui.button("Reset to defaults").clicked() {
+                        *rules = HighlightRule::defaults();
+                    }
+                });
+            });
+        self.open = open;
+        self.unsaved |= *rules != before;
+
+        let dragging = ctx.input(|i| i.pointer.any_down());
+        if self.unsaved && (!open || !(editing || dragging)) {
+            self.unsaved = false;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let mut rules = HighlightRule::defaults();
+        rules[1].alert = true;
+        rules[1].beep = true;
+        let mut highlighter = Highlighter::new();
+        highlighter.sync(&rules);
+
+        assert_eq!(highlighter.rule_for("[ERROR] WARN"), Some(&rules[0]));
+        assert_eq!(
+            highlighter.rule_for("WARNING: low battery"),
+            Some(&rules[1])
+        );
+        assert_eq!(highlighter.rule_for("TOKEN"), None);
+
+        let mut read_data = SerialRead::new(10);
+        for text in ["boot", "WARN 1", "WARN 2", "ok"] {
+            read_data.raw_data.push_back(text.to_string());
+            read_data.timestamps.push_back(Utc::now());
+            read_data.line_counter += 1;
+        }
+        assert_eq!(
+            highlighter.scan(&read_data),
+            Some(Alert {
+                line: 2,
+                beep: true
+            })
+        );
+        // 調べ終わった行では再び警告しない
+        assert_eq!(highlighter.scan(&read_data), None);
+
+        // 無効にしたルールは使わない
+        rules[0].enabled = false;
+        highlighter.sync(&rules);
+        assert_eq!(highlighter.rule_for("[ERROR] WARN"), Some(&rules[1]));
+    }
+}
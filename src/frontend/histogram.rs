@@ -0,0 +1,287 @@
+// src/frontend/histogram.rs
+
+use eframe::egui;
+
+use super::SERIES_COLORS;
+use crate::shared::serial_read::SerialRead;
+
+const DEFAULT_BIN_COUNT: usize = 50;
+const PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinMode {
+    /// 表示範囲をこの数で等分する
+    Count(usize),
+    /// この幅でビンを切る
+    Width(f64),
+}
+
+/// ヒストグラム表示の設定。
+pub struct Histogram {
+    bin_mode: BinMode,
+    /// 系列ごとの表示/非表示。足りない分は表示扱い
+    hidden: Vec<bool>,
+    /// 値のある系列ごとの並べ替えた値と要約。データが変わるまで使い回す
+    sorted: Vec<SortedSeries>,
+    /// `sorted`を計算したときの`SerialRead::revision`
+    sorted_revision: Option<u64>,
+}
+
+/// 1系列分の昇順に並べた値と、その要約。
+struct SortedSeries {
+    index: usize,
+    values: Vec<f64>,
+    distribution: Distribution,
+}
+
+/// 1系列分の分布の要約。
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// `PERCENTILES`に対応する値
+    pub percentiles: [f64; PERCENTILES.len()],
+}
+
+impl Distribution {
+    /// 昇順に並んだ値から要約を計算する。
+    pub fn from_sorted(sorted: &[f64]) -> Option<Self> {
+        if sorted.is_empty() {
+            return None;
+        }
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let variance = sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+        Some(Self {
+            count,
+            mean,
+            std_dev: variance.sqrt(),
+            percentiles: PERCENTILES.map(|p| percentile(sorted, p)),
+        })
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            bin_mode: BinMode::Count(DEFAULT_BIN_COUNT),
+            hidden: Vec::new(),
+            sorted: Vec::new(),
+            sorted_revision: None,
+        }
+    }
+
+    /// データが変わっていれば、全系列の値を並べ替えて要約し直す。
+    fn refresh(&mut self, serial_read: &SerialRead) {
+        if self.sorted_revision == Some(serial_read.revision) {
+            return;
+        }
+        self.sorted_revision = Some(serial_read.revision);
+        self.sorted = serial_read
+            .graph_data
+            .iter()
+            .enumerate()
+            .filter_map(|(index, data)| {
+                let mut values: Vec<f64> = data.iter().map(|(_, value)| value).collect();
+                values.sort_by(f64::total_cmp);
+                let distribution = Distribution::from_sorted(&values)?;
+                Some(SortedSeries {
+                    index,
+                    values,
+                    distribution,
+                })
+            })
+            .collect();
+    }
+
+    fn is_visible(&self, series: usize) -> bool {
+        !self.hidden.get(series).copied().unwrap_or(false)
+    }
+
+    fn ui(&mut self, serial_read: &SerialRead, ui: &mut egui::Ui) {
+        let series_count = serial_read.graph_data.len();
+        ui.horizontal(|ui| {
+            ui.label("Bins:");
+            let mut by_count = matches!(self.bin_mode, BinMode::Count(_));
+            ui.selectable_value(&mut by_count, true, "Count");
+            ui.selectable_value(&mut by_count, false, "Width");
+            match (&mut self.bin_mode, by_count) {
+                (BinMode::Count(count), true) => {
+                    ui.add(egui::DragValue::new(count).range(1..=10_000));
+                }
+                (BinMode::Width(width), false) => {
+                    ui.add(
+                        egui::DragValue::new(width)
+                            .range(f64::MIN_POSITIVE..=f64::MAX)
+                            .speed(0.01),
+                    );
+                }
+                (_, true) => self.bin_mode = BinMode::Count(DEFAULT_BIN_COUNT),
+                (_, false) => self.bin_mode = BinMode::Width(1.0),
+            }
+
+            ui.separator();
+
+            self.hidden
+                .resize(series_count.max(self.hidden.len()), false);
+            for i in 0..series_count {
+                let mut visible = !self.hidden[i];
+                if ui
+                    .checkbox(&mut visible, serial_read.series_name(i))
+                    .changed()
+                {
+                    self.hidden[i] = !visible;
+                }
+            }
+        });
+    }
+
+    pub fn show(&mut self, serial_read: &SerialRead, ui: &mut egui::Ui) {
+        self.ui(serial_read, ui);
+        ui.add_space(5.0);
+
+        self.refresh(serial_read);
+        let series: Vec<&SortedSeries> = self
+            .sorted
+            .iter()
+            .filter(|series| self.is_visible(series.index))
+            .collect();
+
+        egui::Grid::new("histogram stats")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Series");
+                ui.label("Count");
+                ui.label("Mean");
+                ui.label("Std dev");
+                for p in PERCENTILES {
+                    ui.label(format!("P{p}"));
+                }
+                ui.end_row();
+
+                for SortedSeries {
+                    index: i,
+                    distribution,
+                    ..
+                } in &series
+                {
+                    ui.colored_label(
+                        SERIES_COLORS[i % SERIES_COLORS.len()],
+                        serial_read.series_name(*i),
+                    );
+                    ui.label(distribution.count.to_string());
+                    ui.label(format!("{:.4}", distribution.mean));
+                    ui.label(format!("{:.4}", distribution.std_dev));
+                    for value in distribution.percentiles {
+                        ui.label(format!("{value:.4}"));
+                    }
+                    ui.end_row();
+                }
+            });
+
+        ui.add_space(5.0);
+
+        // 重ねて表示できるように、全系列で共通のビンを使う
+        let min = series
+            .iter()
+            .map(|series| series.values[0])
+            .fold(f64::INFINITY, f64::min);
+        let max = series
+            .iter()
+            .map(|series| series.values[series.values.len() - 1])
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        egui_plot::Plot::new("histogram plot")
+            .x_axis_label("Value")
+            .y_axis_label("Count")
+            .legend(egui_plot::Legend::default())
+            .show(ui, |plot_ui| {
+                let Some((start, width, bin_count)) = bin_layout(min, max, self.bin_mode) else {
+                    return;
+                };
+                for SortedSeries {
+                    index: i, values, ..
+                } in &series
+                {
+                    let bars = count_bins(values, start, width, bin_count)
+                        .into_iter()
+                        .enumerate()
+                        .map(|(bin, count)| {
+                            egui_plot::Bar::new(start + (bin as f64 + 0.5) * width, count as f64)
+                                .width(width)
+                        })
+                        .collect();
+                    plot_ui.bar_chart(
+                        egui_plot::BarChart::new(i.to_string(), bars)
+                            .name(serial_read.series_name(*i))
+                            .color(SERIES_COLORS[i % SERIES_COLORS.len()]),
+                    );
+                }
+            });
+    }
+}
+
+/// 値の範囲からビンの開始位置・幅・個数を決める。
+fn bin_layout(min: f64, max: f64, mode: BinMode) -> Option<(f64, f64, usize)> {
+    if !min.is_finite() || !max.is_finite() {
+        return None;
+    }
+    // 全て同じ値の場合でも1本は表示できるようにする
+    let span = if max > min { max - min } else { 1.0 };
+    match mode {
+        BinMode::Count(count) => {
+            let count = count.max(1);
+            Some((min, span / count as f64, count))
+        }
+        BinMode::Width(width) if width > 0.0 => {
+            let count = ((span / width).floor() as usize + 1).min(100_000);
+            Some((min, width, count))
+        }
+        BinMode::Width(_) => None,
+    }
+}
+
+/// 各ビンに入る値の数を数える。最大値は最後のビンに含める。
+fn count_bins(values: &[f64], start: f64, width: f64, bin_count: usize) -> Vec<usize> {
+    let mut counts = vec![0; bin_count];
+    for &value in values {
+        let bin = ((value - start) / width).floor();
+        if bin >= 0.0 {
+            counts[(bin as usize).min(bin_count - 1)] += 1;
+        }
+    }
+    counts
+}
+
+/// 昇順に並んだ値の`p`パーセンタイルを線形補間で求める。
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_and_bins() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+
+        let distribution = Distribution::from_sorted(&values).unwrap();
+        assert_eq!(distribution.count, 5);
+        assert_eq!(distribution.mean, 3.0);
+        assert!((distribution.std_dev - 2.0_f64.sqrt()).abs() < 1e-12);
+        assert_eq!(distribution.percentiles[2], 3.0);
+        assert_eq!(distribution.percentiles[1], 2.0);
+
+        let (start, width, count) = bin_layout(1.0, 5.0, BinMode::Count(4)).unwrap();
+        assert_eq!((start, width, count), (1.0, 1.0, 4));
+        assert_eq!(count_bins(&values, start, width, count), vec![1, 1, 1, 2]);
+
+        let (_, _, count) = bin_layout(1.0, 5.0, BinMode::Width(2.0)).unwrap();
+        assert_eq!(count, 3);
+    }
+}
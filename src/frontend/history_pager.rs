@@ -0,0 +1,49 @@
+// src/frontend/history_pager.rs
+
+use std::io;
+use std::ops::Range;
+
+use crate::shared::history::{DiskHistory, HistoryLine};
+
+/// ディスク上の履歴から最後に読み込んだ範囲を、フレームをまたいで保持する。
+pub struct HistoryPager {
+    generation: Option<u64>,
+    lines: Range<usize>,
+    page: Vec<HistoryLine>,
+}
+
+impl HistoryPager {
+    pub fn new() -> Self {
+        Self {
+            generation: None,
+            lines: 0..0,
+            page: Vec::new(),
+        }
+    }
+
+    /// 行番号が`lines`の範囲にある行を返す。
+    /// 前回読み込んだ範囲に含まれていればファイルを読まない。
+    pub fn page(
+        &mut self,
+        history: &DiskHistory,
+        lines: Range<usize>,
+    ) -> io::Result<&[HistoryLine]> {
+        let available = history.lines();
+        let lines = lines.start.max(available.start)..lines.end.min(available.end);
+        if lines.is_empty() {
+            return Ok(&[]);
+        }
+
+        let cached = self.generation == Some(history.generation())
+            && self.lines.start <= lines.start
+            && lines.end <= self.lines.end;
+        if !cached {
+            self.page = history.read_lines(lines.clone())?;
+            self.lines = lines.clone();
+            self.generation = Some(history.generation());
+        }
+
+        let offset = lines.start - self.lines.start;
+        Ok(&self.page[offset..offset + lines.len()])
+    }
+}
@@ -0,0 +1,563 @@
+// src/frontend/macros.rs
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crossbeam::channel::Sender;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use super::ERROR_COLOR;
+use super::send_options::{LineEnding, SendOptions};
+use super::settings::Settings;
+use crate::shared::{Event, QueuedSend, SharedData};
+
+/// マクロで1回に送信するデータ。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MacroStep {
+    /// 送信する前の待ち時間 [ms]
+    pub delay_ms: u64,
+    pub payload: String,
+    /// `payload`を16進数のバイト列として送る
+    pub hex: bool,
+    pub line_ending: LineEnding,
+    pub interpret_escapes: bool,
+}
+
+impl Default for MacroStep {
+    fn default() -> Self {
+        Self {
+            delay_ms: 0,
+            payload: String::new(),
+            hex: false,
+            line_ending: LineEnding::Lf,
+            interpret_escapes: false,
+        }
+    }
+}
+
+impl MacroStep {
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        SendOptions {
+            line_ending: self.line_ending,
+            hex: self.hex,
+            interpret_escapes: self.interpret_escapes,
+            ..SendOptions::default()
+        }
+        .encode(&self.payload)
+    }
+}
+
+/// マクロを実行するキー。修飾キーなしで使えるのはファンクションキーだけ。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Shortcut {
+    /// Ctrl（macOSではCmd）
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    /// `egui::Key::name`の名前
+    pub key: String,
+}
+
+impl Shortcut {
+    /// 割り当てられるキー。
+    fn keys() -> impl Iterator<Item = egui::Key> {
+        egui::Key::ALL.iter().copied().filter(|key| {
+            let name = key.name();
+            Self::is_function_key(*key)
+                || (name.len() == 1 && name.chars().all(|c| c.is_ascii_alphanumeric()))
+        })
+    }
+
+    fn is_function_key(key: egui::Key) -> bool {
+        key.name()
+            .strip_prefix('F')
+            .is_some_and(|number| number.parse::<u8>().is_ok())
+    }
+
+    /// eguiのショートカット。キーが不正か、文字のキーに修飾キーがなければ`None`。
+    fn keyboard_shortcut(&self) -> Option<egui::KeyboardShortcut> {
+        let key = egui::Key::from_name(&self.key)?;
+        // 文字の入力と取り違えないよう、CtrlかAltを必須にする
+        if !Self::is_function_key(key) && !self.ctrl && !self.alt {
+            return None;
+        }
+        let mut modifiers = egui::Modifiers::NONE;
+        if self.ctrl {
+            modifiers |= egui::Modifiers::COMMAND;
+        }
+        if self.shift {
+            modifiers |= egui::Modifiers::SHIFT;
+        }
+        if self.alt {
+            modifiers |= egui::Modifiers::ALT;
+        }
+        Some(egui::KeyboardShortcut::new(modifiers, key))
+    }
+}
+
+/// 名前を付けた、1つ以上のデータを順に送信するボタン。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Macro {
+    pub name: String,
+    pub shortcut: Option<Shortcut>,
+    pub steps: Vec<MacroStep>,
+}
+
+impl Default for Macro {
+    fn default() -> Self {
+        Self {
+            name: "New macro".to_string(),
+            shortcut: None,
+            steps: vec![MacroStep::default()],
+        }
+    }
+}
+
+impl Macro {
+    /// バックエンドに送る送信データの列。不正なステップがあればエラーメッセージを返す。
+    /// `echo`が真なら、全てのステップをモニタに表示する。
+    pub fn sends(&self, echo: bool) -> Result<Vec<QueuedSend>, String> {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let bytes = step.encode().map_err(|e| format!("Step {}: {e}", i + 1))?;
+                Ok(QueuedSend {
+                    delay: Duration::from_millis(step.delay_ms),
+                    bytes,
+                    // 16進数で送ったバイト列は、モニタで確認できるよう常に表示する
+                    echo: echo || step.hex,
+                })
+            })
+            .collect()
+    }
+}
+
+/// マクロのファイルの形式。チームで共有できるよう、設定とは別のファイルにする。
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct MacroFile {
+    macros: Vec<Macro>,
+}
+
+/// マクロのファイルを読み込む。まだなければ空の一覧を返す。
+pub fn load(path: &Path) -> io::Result<Vec<Macro>> {
+    match fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str::<MacroFile>(&json)
+            .map_err(io::Error::from)?
+            .macros),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn save(path: &Path, macros: &[Macro]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = MacroFile {
+        macros: macros.to_vec(),
+    };
+    fs::write(path, serde_json::to_string_pretty(&file)?)
+}
+
+/// マクロのボタンを並べるパネルと、マクロを編集するウィンドウ。
+pub struct MacroPanel {
+    pub open: bool,
+    macros: Vec<Macro>,
+    /// 読み書きしているファイル
+    path: Option<PathBuf>,
+    /// 別のファイルを開く・保存するときに入力するパス
+    path_input: String,
+    /// 編集中のマクロの添字
+    editing: Option<usize>,
+    /// 保存していない変更がある
+    unsaved: bool,
+}
+
+impl MacroPanel {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            macros: Vec::new(),
+            path: None,
+            path_input: String::new(),
+            editing: None,
+            unsaved: false,
+        }
+    }
+
+    /// 設定に保存されたファイル（なければ設定ディレクトリの`macros.json`）を開く。
+    pub fn load(&mut self, settings: &Settings) -> io::Result<()> {
+        let path = settings
+            .macro_file
+            .clone()
+            .or_else(|| Some(Settings::config_dir()?.join("macros.json")));
+        if let Some(path) = path {
+            self.macros = load(&path)?;
+            self.path_input = path.display().to_string();
+            self.path = Some(path);
+        }
+        Ok(())
+    }
+
+    fn save(&self, shared_data: &SharedData) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = save(path, &self.macros) {
+            eprintln!("Failed to save macros: {e}");
+            *shared_data.error_log.lock() = format!("Failed to save macros: {e}");
+        }
+    }
+
+    fn run(
+        &self,
+        index: usize,
+        settings: &Settings,
+        shared_data: &SharedData,
+        event_sender: &Sender<Event>,
+    ) {
+        match self.macros[index].sends(settings.send_options.echo) {
+            Ok(sends) => event_sender
+                .send(Event::QueueSends(sends))
+                .expect("Failed to send QueueSends event"),
+            Err(e) => {
+                *shared_data.error_log.lock() =
+                    format!("Failed to run macro {}: {e}", self.macros[index].name);
+            }
+        }
+    }
+
+    /// ショートカットキーが押されたマクロを実行する。パネルを閉じていても毎フレーム呼ぶ。
+    pub fn check_shortcuts(
+        &self,
+        ctx: &egui::Context,
+        settings: &Settings,
+        shared_data: &SharedData,
+        event_sender: &Sender<Event>,
+    ) {
+        for (i, macro_) in self.macros.iter().enumerate() {
+            let Some(shortcut) = macro_
+                .shortcut
+                .as_ref()
+                .and_then(Shortcut::keyboard_shortcut)
+            else {
+                continue;
+            };
+            if ctx.input_mut(|input| input.consume_shortcut(&shortcut)) {
+                self.run(i, settings, shared_data, event_sender);
+            }
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        shared_data: &SharedData,
+        settings: &mut Settings,
+        event_sender: &Sender<Event>,
+    ) {
+        egui::SidePanel::right("macro_panel")
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.add_space(5.0);
+                ui.heading("Macros");
+
+                let queued = shared_data.queued_sends.load(Ordering::Relaxed);
+                if queued > 0 {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label(format!("{queued} sends pending"));
+                        if ui.button("Stop").clicked() {
+                            event_sender
+                                .send(Event::CancelQueuedSends)
+                                .expect("Failed to send CancelQueuedSends event");
+                        }
+                    });
+                }
+
+                egui::ScrollArea::vertical()
+                    .max_height(ui.available_height() - 90.0)
+                    .show(ui, |ui| {
+                        for i in 0..self.macros.len() {
+                            ui.horizontal(|ui| {
+                                let macro_ = &self.macros[i];
+                                let mut button = ui.button(&macro_.name);
+                                if let Some(shortcut) = macro_
+                                    .shortcut
+                                    .as_ref()
+                                    .and_then(Shortcut::keyboard_shortcut)
+                                {
+                                    button = button.on_hover_text(ctx.format_shortcut(&shortcut));
+                                }
+                                if button.clicked() {
+                                    self.run(i, settings, shared_data, event_sender);
+                                }
+                                if ui.small_button("✏").on_hover_text("Edit").clicked() {
+                                    self.editing = Some(i);
+                                }
+                            });
+                        }
+                    });
+                if ui.button("Add macro").clicked() {
+                    self.macros.push(Macro::default());
+                    self.editing = Some(self.macros.len() - 1);
+                    self.save(shared_data);
+                }
+
+                ui.separator();
+                ui.label("Macro file");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.path_input).desired_width(f32::INFINITY),
+                );
+                ui.horizontal(|ui| {
+                    if ui
+                        .button("Open")
+                        .on_hover_text("Use the macros in this file")
+                        .clicked()
+                    {
+                        let path = PathBuf::from(self.path_input.trim());
+                        match load(&path) {
+                            Ok(macros) => {
+                                self.macros = macros;
+                                self.editing = None;
+                                self.unsaved = false;
+                                self.switch_file(path, shared_data, settings);
+                            }
+                            Err(e) => {
+                                eprintln!("Failed to open macros: {e}");
+                                *shared_data.error_log.lock() =
+                                    format!("Failed to open macros: {e}");
+                            }
+                        }
+                    }
+                    if ui
+                        .button("Save as")
+                        .on_hover_text("Save the macros to this file and keep using it")
+                        .clicked()
+                    {
+                        let path = PathBuf::from(self.path_input.trim());
+                        self.switch_file(path, shared_data, settings);
+                        self.save(shared_data);
+                    }
+                });
+            });
+
+        if self.editing.is_some() {
+            self.editor(ctx, shared_data);
+        }
+    }
+
+    fn switch_file(&mut self, path: PathBuf, shared_data: &SharedData, settings: &mut Settings) {
+        settings.macro_file = Some(path.clone());
+        self.path = Some(path);
+        settings.save_or_log(shared_data);
+    }
+
+    /// 編集中のマクロのウィンドウ。入力中は保存せず、入力欄からフォーカスが外れるか
+    /// ウィンドウを閉じたときにファイルに保存する。
+    fn editor(&mut self, ctx: &egui::Context, shared_data: &SharedData) {
+        let Some(index) = self.editing.filter(|&index| index < self.macros.len()) else {
+            self.editing = None;
+            return;
+        };
+        let before = self.macros[index].clone();
+        let mut open = true;
+        let mut delete = false;
+        let mut editing = false;
+        egui::Window::new("Edit macro")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let macro_ = &mut self.macros[index];
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    editing |= ui.text_edit_singleline(&mut macro_.name).has_focus();
+                });
+                Self::shortcut_editor(&mut macro_.shortcut, ui);
+                ui.add_space(5.0);
+
+                let mut remove = None;
+                egui::Grid::new("macro steps").striped(true).show(ui, |ui| {
+                    ui.label("Delay");
+                    ui.label("Payload");
+                    ui.label("Hex");
+                    ui.label("Line ending");
+                    ui.label("Escapes");
+                    ui.end_row();
+
+                    for (i, step) in macro_.steps.iter_mut().enumerate() {
+                        ui.add(
+                            egui::DragValue::new(&mut step.delay_ms)
+                                .range(0..=60_000)
+                                .suffix(" ms"),
+                        )
+                        .on_hover_text("Wait before sending this step");
+                        let error = step.encode().err();
+                        let mut payload = egui::TextEdit::singleline(&mut step.payload)
+                            .font(egui::TextStyle::Monospace)
+                            .desired_width(200.0);
+                        if error.is_some() {
+                            payload = payload.text_color(ERROR_COLOR);
+                        }
+                        let response = ui.add(payload);
+                        editing |= response.has_focus();
+                        if let Some(error) = error {
+                            response.on_hover_text(error);
+                        }
+                        ui.checkbox(&mut step.hex, "");
+                        ui.add_enabled_ui(!step.hex, |ui| {
+                            egui::ComboBox::from_id_salt(("line ending", i))
+                                .selected_text(step.line_ending.label())
+                                .show_ui(ui, |ui| {
+                                    for line_ending in LineEnding::ALL {
+                                        ui.selectable_value(
+                                            &mut step.line_ending,
+                                            line_ending,
+                                            line_ending.label(),
+                                        );
+                                    }
+                                });
+                        });
+                        ui.add_enabled(
+                            !step.hex,
+                            egui::Checkbox::without_text(&mut step.interpret_escapes),
+                        );
+                        if ui.small_button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = remove {
+                    macro_.steps.remove(i);
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Add step").clicked() {
+                        // 前のステップの形式を引き継ぐ
+                        let step =
+                            macro_
+                                .steps
+                                .last()
+                                .map_or_else(MacroStep::default, |last| MacroStep {
+                                    delay_ms: last.delay_ms.max(100),
+                                    payload: String::new(),
+                                    ..last.clone()
+                                });
+                        macro_.steps.push(step);
+                    }
+                    if ui.button("Delete macro").clicked() {
+                        delete = true;
+                    }
+                });
+            });
+
+        self.unsaved |= delete || self.macros[index] != before;
+        if delete {
+            self.macros.remove(index);
+            self.editing = None;
+        } else if !open {
+            self.editing = None;
+        }
+
+        let dragging = ctx.input(|i| i.pointer.any_down());
+        if self.unsaved && (self.editing.is_none() || !(editing || dragging)) {
+            self.unsaved = false;
+            self.save(shared_data);
+        }
+    }
+
+    fn shortcut_editor(shortcut: &mut Option<Shortcut>, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let mut enabled = shortcut.is_some();
+            if ui.checkbox(&mut enabled, "Shortcut").changed() {
+                *shortcut = enabled.then(|| Shortcut {
+                    ctrl: false,
+                    shift: false,
+                    alt: false,
+                    key: egui::Key::F1.name().to_string(),
+                });
+            }
+            let Some(shortcut) = shortcut else {
+                return;
+            };
+            ui.toggle_value(&mut shortcut.ctrl, "Ctrl");
+            ui.toggle_value(&mut shortcut.shift, "Shift");
+            ui.toggle_value(&mut shortcut.alt, "Alt");
+            egui::ComboBox::from_id_salt("shortcut key")
+                .selected_text(&shortcut.key)
+                .show_ui(ui, |ui| {
+                    for key in Shortcut::keys() {
+                        ui.selectable_value(&mut shortcut.key, key.name().to_string(), key.name());
+                    }
+                });
+            match shortcut.keyboard_shortcut() {
+                Some(keyboard_shortcut) => {
+                    ui.weak(ui.ctx().format_shortcut(&keyboard_shortcut));
+                }
+                None => {
+                    ui.colored_label(ERROR_COLOR, "Letters and digits need Ctrl or Alt");
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_sends() {
+        let macro_ = Macro {
+            name: "calib".to_string(),
+            shortcut: None,
+            steps: vec![
+                MacroStep {
+                    payload: "calib".to_string(),
+                    line_ending: LineEnding::CrLf,
+                    ..Default::default()
+                },
+                MacroStep {
+                    delay_ms: 500,
+                    payload: "01 A0".to_string(),
+                    hex: true,
+                    ..Default::default()
+                },
+            ],
+        };
+        let sends = macro_.sends(false).unwrap();
+        assert_eq!(sends[0].bytes, b"calib\r\n");
+        assert!(!sends[0].echo);
+        assert_eq!(sends[1].delay, Duration::from_millis(500));
+        assert_eq!(sends[1].bytes, [0x01, 0xa0]);
+        assert!(sends[1].echo);
+
+        let mut broken = macro_.clone();
+        broken.steps[1].payload = "0G".to_string();
+        assert!(macro_.sends(true).unwrap()[0].echo);
+        assert!(broken.sends(false).unwrap_err().starts_with("Step 2"));
+
+        // 文字のキーには修飾キーが必要
+        let mut shortcut = Shortcut {
+            ctrl: false,
+            shift: true,
+            alt: false,
+            key: "R".to_string(),
+        };
+        assert_eq!(shortcut.keyboard_shortcut(), None);
+        shortcut.ctrl = true;
+        assert!(shortcut.keyboard_shortcut().is_some());
+        shortcut.key = "F5".to_string();
+        shortcut.ctrl = false;
+        assert!(shortcut.keyboard_shortcut().is_some());
+    }
+}
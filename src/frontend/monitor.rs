@@ -0,0 +1,1080 @@
+// src/frontend/monitor.rs
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use eframe::egui;
+
+use super::byte_view::{escape_bytes, hex_bytes, hex_dump};
+use super::highlight::{HighlightEditor, HighlightRule, Highlighter};
+use super::history_pager::HistoryPager;
+use super::search::{Search, SearchFilter};
+use super::settings::Settings;
+use crate::shared::SharedData;
+use crate::shared::ansi::{self, AnsiColor, AnsiStyle};
+use crate::shared::history::{DiskHistory, HistoryLine};
+use crate::shared::serial_read::SerialRead;
+
+/// ディスク上の履歴を1ページに何行表示するか
+const PAGE_LINES: usize = 1000;
+
+/// 送信したデータの行の文字色
+const SENT_COLOR: egui::Color32 = egui::Color32::from_rgb(90, 170, 250);
+
+/// コピー用に読み出した1行。
+struct TextLine {
+    line: usize,
+    /// 受信時刻。受信中の行にはない
+    time: Option<DateTime<Utc>>,
+    offset: u64,
+    bytes: Vec<u8>,
+    text: String,
+}
+
+/// 選択中の行。クリックした行が`anchor`、Shift+クリックで`cursor`を動かす。
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Selection {
+    anchor: usize,
+    cursor: usize,
+}
+
+impl Selection {
+    /// 選択中の行番号の範囲
+    fn lines(&self) -> Range<usize> {
+        self.anchor.min(self.cursor)..self.anchor.max(self.cursor) + 1
+    }
+}
+
+/// 行の本文の表示形式。
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ViewMode {
+    /// UTF-8として読んだ文字列
+    Text,
+    /// 表示できる文字はそのまま、制御文字と不正なバイトは`<0x1B>`の形式
+    Mixed,
+    /// 位置、16進数とASCII
+    Hex,
+}
+
+impl ViewMode {
+    const ALL: [ViewMode; 3] = [ViewMode::Text, ViewMode::Mixed, ViewMode::Hex];
+
+    fn label(&self) -> &'static str {
+        match self {
+            ViewMode::Text => "Text",
+            ViewMode::Mixed => "Mixed",
+            ViewMode::Hex => "Hex",
+        }
+    }
+
+    /// 受信したバイト列`bytes`と、それを文字列として読んだ`text`から本文を作る。
+    /// `complete`は改行まで受信済みの行か。16進数の表示では改行のバイトも含める。
+    fn body<'a>(
+        &self,
+        options: TextOptions,
+        offset: u64,
+        bytes: &[u8],
+        text: &'a str,
+        complete: bool,
+    ) -> Body<'a> {
+        let text = match self {
+            ViewMode::Text => {
+                let text = if options.rewrite_carriage_returns {
+                    ansi::rewrite_carriage_returns(text)
+                } else {
+                    text
+                };
+                if options.ansi && text.contains('\x1b') {
+                    let styled = ansi::parse(text);
+                    return Body {
+                        text: Cow::Owned(styled.text),
+                        spans: styled.spans,
+                    };
+                }
+                Cow::Borrowed(text)
+            }
+            ViewMode::Mixed => Cow::Owned(escape_bytes(bytes)),
+            ViewMode::Hex if complete => Cow::Owned(hex_dump(offset, &[bytes, b"\n"].concat())),
+            ViewMode::Hex => Cow::Owned(hex_dump(offset, bytes)),
+        };
+        Body {
+            text,
+            spans: Vec::new(),
+        }
+    }
+}
+
+impl ViewMode {
+    /// 送信したデータの本文。文字列として表示できなければ16進数にする。
+    fn sent_body(&self, bytes: &[u8]) -> String {
+        let text = match self {
+            ViewMode::Text => std::str::from_utf8(bytes)
+                .ok()
+                .map(|text| text.trim_end_matches(['\r', '\n']))
+                .filter(|text| !text.chars().any(|c| c.is_control() && c != '\t')),
+            ViewMode::Mixed => return format!("TX {}", escape_bytes(bytes)),
+            ViewMode::Hex => None,
+        };
+        match text {
+            Some(text) => format!("TX {text}"),
+            None => format!("TX {}", hex_bytes(bytes)),
+        }
+    }
+}
+
+/// 送信したデータを受信した行の間に並べたときの、メモリ上の行の表示の1行。
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LiveRow {
+    /// 行番号の行。受信中の行も含む
+    Line(usize),
+    /// `SerialRead::sent`の添字
+    Sent(usize),
+}
+
+impl LiveRow {
+    /// 上から`row`番目の行。送信したデータは、送信したときに受信中だった行の手前に並べる。
+    fn at(read_data: &SerialRead, row: usize) -> Self {
+        let first_line = read_data.first_line();
+        // k番目の送信したデータは上から(行番号 - first_line + k)番目にある。kについて狭義単調増加
+        let sent_row = |k: usize| read_data.sent[k].line - first_line + k;
+        let (mut low, mut high) = (0, read_data.sent.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if sent_row(mid) < row {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if low < read_data.sent.len() && sent_row(low) == row {
+            LiveRow::Sent(low)
+        } else {
+            LiveRow::Line(first_line + row - low)
+        }
+    }
+
+    /// 行番号`line`の行が上から何番目か。
+    fn of_line(read_data: &SerialRead, line: usize) -> usize {
+        let sent_before = read_data.sent.partition_point(|sent| sent.line <= line);
+        line.saturating_sub(read_data.first_line()) + sent_before
+    }
+}
+
+/// 文字列として表示するときのエスケープシーケンスと`\r`の扱い。
+#[derive(Clone, Copy, Debug)]
+struct TextOptions {
+    /// ANSIエスケープシーケンスの色を表示する。偽ならシーケンスをそのまま表示する
+    ansi: bool,
+    /// `\r`で書き直された行は最後の内容だけを表示する
+    rewrite_carriage_returns: bool,
+}
+
+/// 表示する本文と、その中のANSIエスケープシーケンスによる装飾。
+struct Body<'a> {
+    text: Cow<'a, str>,
+    spans: Vec<(Range<usize>, AnsiStyle)>,
+}
+
+/// ANSIの色をeguiの色にする。16色は一般的な端末の配色を使う。
+fn ansi_color(color: AnsiColor) -> egui::Color32 {
+    const PALETTE: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 49, 49),
+        (13, 188, 121),
+        (229, 229, 16),
+        (36, 114, 200),
+        (188, 63, 188),
+        (17, 168, 205),
+        (229, 229, 229),
+        (102, 102, 102),
+        (241, 76, 76),
+        (35, 209, 139),
+        (245, 245, 67),
+        (59, 142, 234),
+        (214, 112, 214),
+        (41, 184, 219),
+        (255, 255, 255),
+    ];
+    let (r, g, b) = match color {
+        AnsiColor::Rgb(r, g, b) => (r, g, b),
+        AnsiColor::Indexed(index @ 0..16) => PALETTE[index as usize],
+        // 6×6×6の色立方体
+        AnsiColor::Indexed(index @ 16..232) => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        // 24段階のグレー
+        AnsiColor::Indexed(index) => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    };
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// 行の本文の装飾。
+struct Decoration<'a> {
+    /// 本文の中で検索に一致した部分のバイト範囲
+    highlights: Vec<Range<usize>>,
+    /// 選択中の一致した行かどうか
+    current: bool,
+    /// 行に一致した強調表示のルール
+    rule: Option<&'a HighlightRule>,
+    /// ANSIエスケープシーケンスで装飾された部分
+    spans: &'a [(Range<usize>, AnsiStyle)],
+}
+
+/// 各行の先頭に表示する受信時刻の形式。
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimestampMode {
+    Off,
+    /// 時計の時刻（ローカル時刻）
+    Absolute,
+    /// 最初の行を受信してからの経過時間
+    SinceStart,
+    /// 1つ前の行からの経過時間
+    Delta,
+}
+
+impl TimestampMode {
+    const ALL: [TimestampMode; 4] = [
+        TimestampMode::Off,
+        TimestampMode::Absolute,
+        TimestampMode::SinceStart,
+        TimestampMode::Delta,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            TimestampMode::Off => "Off",
+            TimestampMode::Absolute => "Clock",
+            TimestampMode::SinceStart => "Since start",
+            TimestampMode::Delta => "Delta",
+        }
+    }
+
+    /// 受信時刻をミリ秒まで表示する。受信中の行のように時刻がなければ同じ幅の空白にする。
+    fn format(
+        &self,
+        time: Option<DateTime<Utc>>,
+        previous: Option<DateTime<Utc>>,
+        start: Option<DateTime<Utc>>,
+    ) -> String {
+        fn seconds(delta: TimeDelta) -> f64 {
+            delta.num_milliseconds() as f64 / 1000.0
+        }
+
+        let text = match (self, time) {
+            (TimestampMode::Off, _) => return String::new(),
+            (TimestampMode::Absolute, Some(time)) => time
+                .with_timezone(&Local)
+                .format("%H:%M:%S%.3f")
+                .to_string(),
+            (TimestampMode::SinceStart, Some(time)) => start
+                .map(|start| format!("{:.3}", seconds(time - start)))
+                .unwrap_or_default(),
+            (TimestampMode::Delta, Some(time)) => previous
+                .map(|previous| format!("+{:.3}", seconds(time - previous)))
+                .unwrap_or_default(),
+            (_, None) => String::new(),
+        };
+        format!("{text:>12}  ")
+    }
+}
+
+/// 行の先頭に付ける行番号と受信時刻の設定。表示とコピーで共通に使う。
+#[derive(Clone, Copy, Debug)]
+struct Prefix {
+    line_numbers: bool,
+    timestamp: TimestampMode,
+    /// 行番号の欄の桁数
+    digits: usize,
+    start_time: Option<DateTime<Utc>>,
+}
+
+impl Prefix {
+    /// `previous`は1つ前の行の受信時刻。差分の表示に使う。
+    fn format(
+        &self,
+        line: usize,
+        time: Option<DateTime<Utc>>,
+        previous: Option<DateTime<Utc>>,
+    ) -> String {
+        let mut prefix = String::new();
+        if self.line_numbers {
+            prefix += &format!("{:>digits$}  ", line + 1, digits = self.digits);
+        }
+        prefix += &self.timestamp.format(time, previous, self.start_time);
+        prefix
+    }
+
+    /// 送信したデータの行の先頭。行番号の欄は空ける。
+    fn format_sent(&self, time: DateTime<Utc>, previous: Option<DateTime<Utc>>) -> String {
+        let mut prefix = String::new();
+        if self.line_numbers {
+            prefix += &format!("{:>digits$}  ", "", digits = self.digits);
+        }
+        prefix += &self.timestamp.format(Some(time), previous, self.start_time);
+        prefix
+    }
+}
+
+/// シリアルモニタの表示状態。
+pub struct Monitor {
+    /// 最新の行に追従するかどうか
+    follow: bool,
+    /// 上にスクロールして追従が止まったときの総行数。`None`なら追従中
+    paused_at: Option<usize>,
+    /// 次のフレームで最下部までスクロールする
+    scroll_to_bottom: bool,
+
+    /// ディスク上の履歴を表示しているときのページの先頭の行番号。`None`ならメモリ上の行を表示する。
+    page: Option<usize>,
+    pager: HistoryPager,
+
+    selection: Option<Selection>,
+
+    line_numbers: bool,
+    timestamp_mode: TimestampMode,
+    view_mode: ViewMode,
+    text_options: TextOptions,
+
+    search: Search,
+    /// 次のフレームでこの行番号の行までスクロールする
+    scroll_to_line: Option<usize>,
+
+    highlighter: Highlighter,
+    highlight_editor: HighlightEditor,
+    /// 警告するルールに一致した、まだ確認していない最新の行
+    alert: Option<usize>,
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Self {
+            follow: true,
+            paused_at: None,
+            scroll_to_bottom: false,
+            page: None,
+            pager: HistoryPager::new(),
+            selection: None,
+            line_numbers: true,
+            timestamp_mode: TimestampMode::Off,
+            view_mode: ViewMode::Text,
+            text_options: TextOptions {
+                ansi: true,
+                rewrite_carriage_returns: false,
+            },
+            search: Search::new(),
+            scroll_to_line: None,
+            highlighter: Highlighter::new(),
+            highlight_editor: HighlightEditor::new(),
+            alert: None,
+        }
+    }
+
+    /// 1行を行番号・受信時刻の欄と本文で描画し、クリックで選択する。
+    fn row(
+        selection: &mut Option<Selection>,
+        line: usize,
+        prefix: &str,
+        text: &str,
+        decoration: &Decoration,
+        ui: &mut egui::Ui,
+    ) {
+        let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+        let mut job = egui::text::LayoutJob::default();
+        job.append(
+            prefix,
+            0.0,
+            egui::TextFormat::simple(font_id.clone(), ui.visuals().weak_text_color()),
+        );
+        let rule = decoration.rule;
+        let color = rule
+            .and_then(HighlightRule::foreground)
+            .unwrap_or(ui.visuals().text_color());
+        let normal = egui::TextFormat::simple(font_id, color);
+
+        // 装飾が変わる位置で本文を区切る
+        let mut boundaries = vec![0, text.len()];
+        for range in decoration
+            .spans
+            .iter()
+            .map(|(range, _)| range)
+            .chain(&decoration.highlights)
+        {
+            boundaries.extend([range.start, range.end]);
+        }
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        for segment in boundaries.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+            let mut format = normal.clone();
+            if let Some((_, style)) = decoration
+                .spans
+                .iter()
+                .find(|(range, _)| range.contains(&start))
+            {
+                Self::apply_ansi_style(&mut format, style, ui.visuals());
+            }
+            // 検索に一致した部分はANSIの色より優先する
+            if decoration
+                .highlights
+                .iter()
+                .any(|range| range.contains(&start))
+            {
+                format.color = egui::Color32::BLACK;
+                format.background = egui::Color32::from_rgb(255, 210, 0);
+            }
+            job.append(&text[start..end], 0.0, format);
+        }
+        let galley = ui.fonts(|fonts| fonts.layout_job(job));
+
+        // 行の高さを揃えるため、ウィジェットを使わずに直接描画する
+        let size = egui::vec2(
+            galley.size().x.max(ui.available_width()),
+            ui.text_style_height(&egui::TextStyle::Monospace),
+        );
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+        if let Some(background) = rule.and_then(HighlightRule::background) {
+            ui.painter().rect_filled(rect, 0.0, background);
+        }
+        if selection.is_some_and(|s| s.lines().contains(&line)) {
+            ui.painter()
+                .rect_filled(rect, 0.0, ui.visuals().selection.bg_fill);
+        }
+        if decoration.current {
+            ui.painter().rect_stroke(
+                rect,
+                0.0,
+                ui.visuals().selection.stroke,
+                egui::StrokeKind::Inside,
+            );
+        }
+        if rule.is_some_and(|rule| rule.bold) {
+            // 等幅フォントに太字がないので、少しずらして重ね描きする
+            ui.painter().galley(
+                rect.min + egui::vec2(0.7, 0.0),
+                galley.clone(),
+                ui.visuals().text_color(),
+            );
+        }
+        ui.painter()
+            .galley(rect.min, galley, ui.visuals().text_color());
+
+        if response.clicked() {
+            let extend = ui.input(|i| i.modifiers.shift);
+            *selection = match *selection {
+                Some(Selection { anchor, .. }) if extend => Some(Selection {
+                    anchor,
+                    cursor: line,
+                }),
+                _ => Some(Selection {
+                    anchor: line,
+                    cursor: line,
+                }),
+            };
+        }
+    }
+
+    /// 送信したデータの1行。選択やコピーの対象にはしない。
+    fn sent_row(prefix: &str, body: &str, ui: &mut egui::Ui) {
+        let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+        let mut job = egui::text::LayoutJob::default();
+        job.append(
+            prefix,
+            0.0,
+            egui::TextFormat::simple(font_id.clone(), ui.visuals().weak_text_color()),
+        );
+        job.append(body, 0.0, egui::TextFormat::simple(font_id, SENT_COLOR));
+        let galley = ui.fonts(|fonts| fonts.layout_job(job));
+
+        let size = egui::vec2(
+            galley.size().x.max(ui.available_width()),
+            ui.text_style_height(&egui::TextStyle::Monospace),
+        );
+        let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        ui.painter()
+            .rect_filled(rect, 0.0, SENT_COLOR.gamma_multiply(0.08));
+        ui.painter()
+            .galley(rect.min, galley, ui.visuals().text_color());
+    }
+
+    fn apply_ansi_style(format: &mut egui::TextFormat, style: &AnsiStyle, visuals: &egui::Visuals) {
+        let foreground = match style.foreground {
+            // 太字の標準色は明るい色で表示する
+            Some(AnsiColor::Indexed(index @ 0..8)) if style.bold => {
+                Some(AnsiColor::Indexed(index + 8))
+            }
+            foreground => foreground,
+        };
+        if let Some(foreground) = foreground {
+            format.color = ansi_color(foreground);
+        } else if style.bold {
+            format.color = visuals.strong_text_color();
+        }
+        if let Some(background) = style.background {
+            format.background = ansi_color(background);
+        }
+        format.italics = style.italic;
+        if style.underline {
+            format.underline = egui::Stroke::new(1.0, format.color);
+        }
+    }
+
+    /// `text`を受信した行、`body`を表示する本文として装飾を決める。
+    /// 強調表示のルールはエスケープシーケンスを取り除いた行に対して調べる。
+    fn decoration<'a>(
+        search: &Search,
+        highlighter: &'a Highlighter,
+        line: usize,
+        text: &str,
+        body: &'a Body,
+    ) -> Decoration<'a> {
+        Decoration {
+            highlights: search.highlights(&body.text),
+            current: search.current == Some(line),
+            rule: highlighter.rule_for(&ansi::strip(text)),
+            spans: &body.spans,
+        }
+    }
+
+    /// 警告するルールに一致した行が届いていないか調べる。モニタを表示していなくても毎フレーム呼ぶ。
+    pub fn check_alerts(
+        &mut self,
+        read_data: &SerialRead,
+        settings: &Settings,
+        ctx: &egui::Context,
+    ) {
+        self.highlighter.sync(&settings.highlight_rules);
+        if let Some(alert) = self.highlighter.scan(read_data) {
+            self.alert = Some(alert.line);
+            ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                egui::UserAttentionType::Informational,
+            ));
+            if alert.beep {
+                // 端末のベルを鳴らす。標準出力はパイプされることがあるので標準エラー出力に書く。
+                // 音が鳴るかは端末の設定次第で、端末から起動していなければ何も起きない
+                let mut stderr = io::stderr();
+                if let Err(e) = stderr.write_all(b"\x07").and_then(|()| stderr.flush()) {
+                    eprintln!("Failed to ring the bell: {e}");
+                }
+            }
+        }
+    }
+
+    fn prefix(&self, read_data: &SerialRead) -> Prefix {
+        Prefix {
+            line_numbers: self.line_numbers,
+            timestamp: self.timestamp_mode,
+            // 行番号の欄の幅は総行数の桁数に合わせる
+            digits: (read_data.line_counter + 1).to_string().len(),
+            start_time: read_data.start_time,
+        }
+    }
+
+    /// 行番号が`lines`の範囲にある行。メモリから押し出された行はディスクから読む。
+    fn read_lines(read_data: &SerialRead, lines: Range<usize>) -> io::Result<Vec<TextLine>> {
+        let first_line = read_data.first_line();
+
+        let mut result = Vec::with_capacity(lines.len());
+        if let Some(history) = &read_data.disk_history
+            && lines.start < first_line
+        {
+            for line in history.read_lines(lines.start..lines.end.min(first_line))? {
+                result.push(TextLine {
+                    line: line.line,
+                    time: Some(line.time),
+                    offset: line.offset,
+                    bytes: line.bytes,
+                    text: line.text,
+                });
+            }
+        }
+        // 最後の行は受信中の行
+        for line in lines.start.max(first_line)..lines.end.min(read_data.line_counter + 1) {
+            let text = match read_data.raw_data.get(line - first_line) {
+                Some(text) => text,
+                None => &read_data.current_line,
+            };
+            result.push(TextLine {
+                line,
+                time: read_data.timestamp(line),
+                offset: read_data.offset(line).unwrap_or_default(),
+                bytes: read_data.bytes(line).unwrap_or_default().to_vec(),
+                text: text.clone(),
+            });
+        }
+        Ok(result)
+    }
+
+    /// 選択中の行を、表示中の形式で行番号や受信時刻を付けて改行でつなげる。
+    fn selected_text(&self, read_data: &SerialRead) -> io::Result<String> {
+        let Some(selection) = self.selection else {
+            return Ok(String::new());
+        };
+        let lines = selection.lines();
+        let prefix = self.prefix(read_data);
+
+        // 選択範囲の行の間に送信したデータも、表示と同じ位置に入れる
+        let mut sent = read_data
+            .sent
+            .iter()
+            .filter(|sent| lines.start < sent.line && sent.line < lines.end)
+            .peekable();
+
+        // 差分を求めるため、選択範囲の1行前から読む
+        let mut previous = None;
+        let mut texts = Vec::with_capacity(lines.len());
+        for line in Self::read_lines(read_data, lines.start.saturating_sub(1)..lines.end)? {
+            if line.line >= lines.start {
+                while let Some(sent) = sent.next_if(|sent| sent.line <= line.line) {
+                    texts.push(format!(
+                        "{}{}",
+                        prefix.format_sent(sent.time, previous),
+                        self.view_mode.sent_body(&sent.bytes)
+                    ));
+                }
+                texts.push(format!(
+                    "{}{}",
+                    prefix.format(line.line, line.time, previous),
+                    self.view_mode
+                        .body(
+                            self.text_options,
+                            line.offset,
+                            &line.bytes,
+                            &line.text,
+                            line.line < read_data.line_counter,
+                        )
+                        .text
+                ));
+            }
+            previous = line.time;
+        }
+        Ok(texts.join("\n"))
+    }
+
+    fn copy_selection(&self, shared_data: &SharedData, read_data: &SerialRead, ui: &egui::Ui) {
+        match self.selected_text(read_data) {
+            Ok(text) => ui.ctx().copy_text(text),
+            Err(e) => {
+                eprintln!("Failed to read history file: {e}");
+                *shared_data.error_log.lock() = format!("Failed to read history file: {e}");
+            }
+        }
+    }
+
+    /// ディスク上の履歴のページ送り。
+    fn history_controls(&mut self, history: &DiskHistory, ui: &mut egui::Ui) {
+        let lines = history.lines();
+        if let Some(start) = self.page.as_mut() {
+            *start = (*start).clamp(lines.start, lines.end - 1);
+        }
+
+        ui.label(format!(
+            "{} older lines on disk ({:.1} MB)",
+            lines.len(),
+            history.len() as f64 / 1_000_000.0
+        ));
+        if ui.button("Older").clicked() {
+            let start = self.page.unwrap_or(lines.end);
+            self.page = Some(start.saturating_sub(PAGE_LINES).max(lines.start));
+        }
+        if let Some(start) = self.page {
+            if ui.button("Newer").clicked() {
+                // 最後のページの次はメモリ上の行に戻る
+                let next = start + PAGE_LINES;
+                self.page = (next < lines.end).then_some(next);
+            }
+            if ui.button("Live").clicked() {
+                self.page = None;
+            }
+            ui.label(format!(
+                "Lines {}-{}",
+                start + 1,
+                (start + PAGE_LINES).min(lines.end)
+            ));
+        }
+    }
+
+    /// 検索バー。一致した行へ移動したらその行番号を返す。
+    fn search_bar(&mut self, read_data: &SerialRead, ui: &mut egui::Ui) -> Option<usize> {
+        let mut step = None;
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut self.search.query)
+                .hint_text("Search")
+                .desired_width(200.0),
+        );
+        // Enterで次、Shift+Enterで前の一致した行へ移動する
+        if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            step = Some(!ui.input(|i| i.modifiers.shift));
+            response.request_focus();
+        }
+        ui.checkbox(&mut self.search.regex, "Regex");
+        ui.checkbox(&mut self.search.case_sensitive, "Match case");
+        self.search.update(read_data);
+
+        if let Some(error) = self.search.error() {
+            ui.colored_label(ui.visuals().error_fg_color, "Invalid regex")
+                .on_hover_text(error);
+            return None;
+        }
+        self.search.pattern()?;
+
+        if ui.button("⬆").on_hover_text("Previous match").clicked() {
+            step = Some(false);
+        }
+        if ui.button("⬇").on_hover_text("Next match").clicked() {
+            step = Some(true);
+        }
+        let count = self.search.match_count();
+        match self.search.current_index() {
+            Some(index) => ui.label(format!("{index} / {count} matches")),
+            None => ui.label(format!("{count} matches")),
+        };
+        egui::ComboBox::from_label("Show")
+            .selected_text(self.search.filter.label())
+            .show_ui(ui, |ui| {
+                for filter in SearchFilter::ALL {
+                    ui.selectable_value(&mut self.search.filter, filter, filter.label());
+                }
+            });
+
+        step.and_then(|forward| self.search.step(forward))
+    }
+
+    pub fn show(&mut self, shared_data: &SharedData, settings: &mut Settings, ui: &mut egui::Ui) {
+        if self
+            .highlight_editor
+            .show(ui.ctx(), &mut settings.highlight_rules)
+        {
+            self.highlighter.sync(&settings.highlight_rules);
+            settings.save_or_log(shared_data);
+        }
+
+        let read_data = shared_data.read_data.read();
+        let history = read_data
+            .disk_history
+            .as_ref()
+            .filter(|history| !history.is_empty());
+        if history.is_none() {
+            self.page = None;
+        }
+
+        ui.horizontal(|ui| {
+            if ui.checkbox(&mut self.follow, "Follow").changed() && self.follow {
+                self.scroll_to_bottom = true;
+            }
+
+            // 追従が止まっている間に届いた行数を表示し、クリックで最新の行に戻る
+            if let Some(paused_at) = self.paused_at {
+                let new_lines = read_data.line_counter.saturating_sub(paused_at);
+                let text = match new_lines {
+                    0 => "Paused".to_string(),
+                    1 => "1 new line".to_string(),
+                    n => format!("{n} new lines"),
+                };
+                if ui.button(format!("⬇ {text}")).clicked() {
+                    self.paused_at = None;
+                    self.scroll_to_bottom = true;
+                }
+            }
+
+            ui.separator();
+            ui.checkbox(&mut self.line_numbers, "Line numbers");
+            egui::ComboBox::from_label("Time")
+                .selected_text(self.timestamp_mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in TimestampMode::ALL {
+                        ui.selectable_value(&mut self.timestamp_mode, mode, mode.label());
+                    }
+                });
+            egui::ComboBox::from_label("View")
+                .selected_text(self.view_mode.label())
+                .show_ui(ui, |ui| {
+                    for mode in ViewMode::ALL {
+                        ui.selectable_value(&mut self.view_mode, mode, mode.label());
+                    }
+                });
+            if self.view_mode == ViewMode::Text {
+                ui.checkbox(&mut self.text_options.ansi, "ANSI colors")
+                    .on_hover_text("Render color escape sequences and hide cursor movement");
+                ui.checkbox(&mut self.text_options.rewrite_carriage_returns, "Apply \\r")
+                    .on_hover_text(
+                        "Show only the last rewrite of lines that use \\r, like progress bars",
+                    );
+            }
+
+            if ui
+                .selectable_label(self.highlight_editor.open, "Highlight")
+                .clicked()
+            {
+                self.highlight_editor.open = !self.highlight_editor.open;
+            }
+
+            ui.separator();
+            if ui
+                .add_enabled(self.selection.is_some(), egui::Button::new("Copy"))
+                .on_hover_text("Click a line and Shift+click another to select a range")
+                .clicked()
+            {
+                self.copy_selection(shared_data, &read_data, ui);
+            }
+            if let Some(selection) = self.selection {
+                ui.label(format!("{} lines selected", selection.lines().len()));
+                if ui.button("Clear selection").clicked() {
+                    self.selection = None;
+                }
+            }
+
+            if let Some(history) = history {
+                ui.separator();
+                self.history_controls(history, ui);
+            }
+        });
+        ui.horizontal(|ui| {
+            // 一致した行へ移動するときは追従をやめ、メモリ上の行の表示に戻る
+            let mut jump_to = self.search_bar(&read_data, ui);
+
+            if let Some(line) = self.alert {
+                ui.separator();
+                let alert = egui::Button::new(
+                    egui::RichText::new(format!("⚠ Alert at line {}", line + 1))
+                        .color(egui::Color32::WHITE),
+                )
+                .fill(ui.visuals().error_fg_color);
+                if ui.add(alert).clicked() {
+                    jump_to = Some(line);
+                    self.alert = None;
+                }
+                if ui.small_button("Dismiss").clicked() {
+                    self.alert = None;
+                }
+            }
+
+            if let Some(line) = jump_to {
+                self.follow = false;
+                self.page = None;
+                self.scroll_to_line = Some(line);
+            }
+        });
+        ui.add_space(5.0);
+
+        // 入力欄にフォーカスがなければ、Ctrl+Cで選択中の行をコピーする
+        let copy_requested = ui.memory(|memory| memory.focused().is_none())
+            && ui.input(|i| i.events.iter().any(|e| matches!(e, egui::Event::Copy)));
+        if copy_requested && self.selection.is_some() {
+            self.copy_selection(shared_data, &read_data, ui);
+        }
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let prefix = self.prefix(&read_data);
+
+        if let (Some(start), Some(history)) = (self.page, history) {
+            // 差分を求めるため、ページの1行前から読む
+            let range = start.saturating_sub(1)..start + PAGE_LINES;
+            let lines: &[HistoryLine] = match self.pager.page(history, range) {
+                Ok(lines) => lines,
+                Err(e) => {
+                    eprintln!("Failed to read history file: {e}");
+                    *shared_data.error_log.lock() = format!("Failed to read history file: {e}");
+                    &[]
+                }
+            };
+            // 1行前の行は表示しない
+            let skip = usize::from(lines.first().is_some_and(|line| line.line < start));
+            egui::ScrollArea::vertical()
+                .id_salt("monitor_history")
+                .scroll([true, true])
+                .auto_shrink(false)
+                .show_rows(ui, row_height, lines.len() - skip, |ui, rows| {
+                    for row in rows.start + skip..rows.end + skip {
+                        let line = &lines[row];
+                        let previous = row.checked_sub(1).map(|row| lines[row].time);
+                        let body = self.view_mode.body(
+                            self.text_options,
+                            line.offset,
+                            &line.bytes,
+                            &line.text,
+                            true,
+                        );
+                        Self::row(
+                            &mut self.selection,
+                            line.line,
+                            &prefix.format(line.line, Some(line.time), previous),
+                            &body.text,
+                            &Self::decoration(
+                                &self.search,
+                                &self.highlighter,
+                                line.line,
+                                &line.text,
+                                &body,
+                            ),
+                            ui,
+                        );
+                    }
+                });
+            return;
+        }
+
+        let mut scroll_area = egui::ScrollArea::vertical()
+            .scroll([true, true])
+            .auto_shrink(false)
+            .stick_to_bottom(self.follow);
+        if std::mem::take(&mut self.scroll_to_bottom) {
+            // 範囲外の値は最下部に丸められる
+            scroll_area = scroll_area.vertical_scroll_offset(f32::MAX);
+        }
+
+        // 見えている行だけを描画する。最後の行は受信中の行で、送信したデータは受信した行の間に並べる
+        // 検索で絞り込んでいる場合は、確定した行のうち条件に合う行だけを表示する
+        let first_line = read_data.first_line();
+        let filtered = self.search.filtered();
+        let total_rows = filtered.map_or(
+            read_data.raw_data.len() + 1 + read_data.sent.len(),
+            |lines| lines.len(),
+        );
+        if let Some(line) = self.scroll_to_line.take() {
+            let row = match filtered {
+                Some(lines) => lines.partition_point(|&l| l < line),
+                None => LiveRow::of_line(&read_data, line),
+            };
+            // 移動先の行が画面の中央に来るようにする
+            let row_height_with_spacing = row_height + ui.spacing().item_spacing.y;
+            let offset = row as f32 * row_height_with_spacing - ui.available_height() / 2.0;
+            scroll_area = scroll_area.vertical_scroll_offset(offset.max(0.0));
+        }
+        let output = scroll_area.show_rows(ui, row_height, total_rows, |ui, rows| {
+            for row in rows {
+                let line = match filtered {
+                    Some(lines) => lines[row],
+                    None => match LiveRow::at(&read_data, row) {
+                        LiveRow::Line(line) => line,
+                        LiveRow::Sent(index) => {
+                            let sent = &read_data.sent[index];
+                            // 差分は直前に受信した行からの時間にする
+                            let previous = sent
+                                .line
+                                .checked_sub(1)
+                                .and_then(|line| read_data.timestamp(line));
+                            Self::sent_row(
+                                &prefix.format_sent(sent.time, previous),
+                                &self.view_mode.sent_body(&sent.bytes),
+                                ui,
+                            );
+                            continue;
+                        }
+                    },
+                };
+                let text = read_data
+                    .raw_data
+                    .get(line - first_line)
+                    .unwrap_or(&read_data.current_line);
+                let previous = line
+                    .checked_sub(1)
+                    .and_then(|line| read_data.timestamp(line));
+                let body = self.view_mode.body(
+                    self.text_options,
+                    read_data.offset(line).unwrap_or_default(),
+                    read_data.bytes(line).unwrap_or_default(),
+                    text,
+                    line < read_data.line_counter,
+                );
+                Self::row(
+                    &mut self.selection,
+                    line,
+                    &prefix.format(line, read_data.timestamp(line), previous),
+                    &body.text,
+                    &Self::decoration(&self.search, &self.highlighter, line, text, &body),
+                    ui,
+                );
+            }
+        });
+
+        // 最下部から離れたら追従を一時停止する
+        let max_offset = output.content_size.y - output.inner_rect.height();
+        let at_bottom = max_offset <= 0.0 || output.state.offset.y >= max_offset - 1.0;
+        if !self.follow || at_bottom {
+            self.paused_at = None;
+        } else if self.paused_at.is_none() {
+            self.paused_at = Some(read_data.line_counter);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::serial_read::SentData;
+
+    #[test]
+    fn test_prefix() {
+        let start = DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        let time = start + TimeDelta::milliseconds(1500);
+        let previous = start + TimeDelta::milliseconds(1488);
+        let prefix = |timestamp| Prefix {
+            line_numbers: true,
+            timestamp,
+            digits: 3,
+            start_time: Some(start),
+        };
+
+        assert_eq!(
+            prefix(TimestampMode::Off).format(4, Some(time), None),
+            "  5  "
+        );
+        assert_eq!(
+            prefix(TimestampMode::SinceStart).format(4, Some(time), None),
+            "  5         1.500  "
+        );
+        assert_eq!(
+            prefix(TimestampMode::Delta).format(4, Some(time), Some(previous)),
+            "  5        +0.012  "
+        );
+        // 受信中の行は時刻の欄を空けて揃える
+        assert_eq!(
+            prefix(TimestampMode::Absolute).format(4, None, None),
+            format!("  5  {}  ", " ".repeat(12))
+        );
+    }
+
+    #[test]
+    fn test_live_rows() {
+        let mut read_data = SerialRead::new(10);
+        for _ in 0..3 {
+            read_data.raw_data.push_back(String::new());
+            read_data.timestamps.push_back(Utc::now());
+        }
+        read_data.line_counter = 3;
+        // 行1の前に2つ、受信中の行3の前に1つ送信した
+        for line in [1, 1, 3] {
+            read_data.sent.push_back(SentData {
+                line,
+                time: Utc::now(),
+                bytes: Box::new([]),
+            });
+        }
+
+        let rows: Vec<_> = (0..7).map(|row| LiveRow::at(&read_data, row)).collect();
+        assert_eq!(
+            rows,
+            [
+                LiveRow::Line(0),
+                LiveRow::Sent(0),
+                LiveRow::Sent(1),
+                LiveRow::Line(1),
+                LiveRow::Line(2),
+                LiveRow::Sent(2),
+                LiveRow::Line(3),
+            ]
+        );
+        for (row, live_row) in rows.into_iter().enumerate() {
+            if let LiveRow::Line(line) = live_row {
+                assert_eq!(LiveRow::of_line(&read_data, line), row);
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+// src/frontend/search.rs
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+
+use crate::shared::ansi;
+use crate::shared::serial_read::SerialRead;
+
+/// 検索中にどの行を表示するか。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFilter {
+    All,
+    Matching,
+    NonMatching,
+}
+
+impl SearchFilter {
+    pub const ALL: [SearchFilter; 3] = [
+        SearchFilter::All,
+        SearchFilter::Matching,
+        SearchFilter::NonMatching,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchFilter::All => "All lines",
+            SearchFilter::Matching => "Matching",
+            SearchFilter::NonMatching => "Not matching",
+        }
+    }
+}
+
+/// パターンを作り直す条件
+#[derive(Clone, Debug, PartialEq)]
+struct PatternKey {
+    query: String,
+    regex: bool,
+    case_sensitive: bool,
+}
+
+/// シリアルモニタの検索。メモリ上の確定した行を、届いた分だけ順に調べて一致した行を覚えておく。
+pub struct Search {
+    pub query: String,
+    /// `query`を正規表現として扱う。偽なら文字列そのものを探す
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub filter: SearchFilter,
+
+    key: Option<PatternKey>,
+    /// `query`から作ったパターン。空の検索なら`None`、不正な正規表現ならエラーメッセージ
+    pattern: Option<Result<Regex, String>>,
+
+    /// 一致した行と一致しなかった行の行番号。昇順
+    matches: VecDeque<usize>,
+    non_matches: VecDeque<usize>,
+    /// 次に調べる行の行番号
+    scanned_end: usize,
+    /// 調べたときの最初の行の受信時刻。クリアされて変わったら調べ直す
+    session: Option<DateTime<Utc>>,
+
+    /// 選択中の一致した行
+    pub current: Option<usize>,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            regex: false,
+            case_sensitive: false,
+            filter: SearchFilter::All,
+            key: None,
+            pattern: None,
+            matches: VecDeque::new(),
+            non_matches: VecDeque::new(),
+            scanned_end: 0,
+            session: None,
+            current: None,
+        }
+    }
+
+    /// 検索できるパターン。検索していないか、正規表現が不正なら`None`。
+    pub fn pattern(&self) -> Option<&Regex> {
+        self.pattern.as_ref()?.as_ref().ok()
+    }
+
+    /// 正規表現が不正な場合のエラーメッセージ。
+    pub fn error(&self) -> Option<&str> {
+        self.pattern.as_ref()?.as_ref().err().map(String::as_str)
+    }
+
+    /// 一致した行の数。
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// 選択中の一致した行が何番目か（1から数える）。
+    pub fn current_index(&self) -> Option<usize> {
+        let current = self.current?;
+        self.matches
+            .binary_search(&current)
+            .ok()
+            .map(|index| index + 1)
+    }
+
+    /// 絞り込みが有効なら、表示する行の行番号。
+    pub fn filtered(&self) -> Option<&VecDeque<usize>> {
+        self.pattern()?;
+        match self.filter {
+            SearchFilter::All => None,
+            SearchFilter::Matching => Some(&self.matches),
+            SearchFilter::NonMatching => Some(&self.non_matches),
+        }
+    }
+
+    /// `text`の中で一致した部分のバイト範囲。
+    pub fn highlights(&self, text: &str) -> Vec<Range<usize>> {
+        match self.pattern() {
+            Some(pattern) => pattern
+                .find_iter(text)
+                .filter(|m| !m.is_empty())
+                .map(|m| m.range())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// 入力が変わっていればパターンを作り直し、まだ調べていない行を調べる。毎フレーム呼ぶ。
+    pub fn update(&mut self, read_data: &SerialRead) {
+        let key = PatternKey {
+            query: self.query.clone(),
+            regex: self.regex,
+            case_sensitive: self.case_sensitive,
+        };
+        if self.key.as_ref() != Some(&key) {
+            self.pattern = (!key.query.is_empty()).then(|| {
+                let source = if key.regex {
+                    key.query.clone()
+                } else {
+                    regex::escape(&key.query)
+                };
+                RegexBuilder::new(&source)
+                    .case_insensitive(!key.case_sensitive)
+                    .build()
+                    .map_err(|e| e.to_string())
+            });
+            self.key = Some(key);
+            self.reset();
+        }
+        if self.session != read_data.start_time || self.scanned_end > read_data.line_counter {
+            self.session = read_data.start_time;
+            self.reset();
+        }
+
+        // 保持する範囲から押し出された行を忘れる
+        let first_line = read_data.first_line();
+        for lines in [&mut self.matches, &mut self.non_matches] {
+            let count = lines.partition_point(|&line| line < first_line);
+            lines.drain(..count);
+        }
+        if self.current.is_some_and(|line| line < first_line) {
+            self.current = None;
+        }
+
+        let Some(Ok(pattern)) = &self.pattern else {
+            return;
+        };
+        for line in self.scanned_end.max(first_line)..read_data.line_counter {
+            // 表示と同じく、エスケープシーケンスを取り除いた行を調べる
+            if pattern.is_match(&ansi::strip(&read_data.raw_data[line - first_line])) {
+                self.matches.push_back(line);
+            } else {
+                self.non_matches.push_back(line);
+            }
+        }
+        self.scanned_end = read_data.line_counter;
+    }
+
+    fn reset(&mut self) {
+        self.matches.clear();
+        self.non_matches.clear();
+        self.scanned_end = 0;
+        self.current = None;
+    }
+
+    /// 次（`forward`が偽なら前）の一致した行を選択し、その行番号を返す。端まで行くと反対の端に戻る。
+    pub fn step(&mut self, forward: bool) -> Option<usize> {
+        let next = match (self.current, forward) {
+            (Some(current), true) => self
+                .matches
+                .iter()
+                .find(|&&line| line > current)
+                .or(self.matches.front()),
+            (Some(current), false) => self
+                .matches
+                .iter()
+                .rev()
+                .find(|&&line| line < current)
+                .or(self.matches.back()),
+            (None, true) => self.matches.front(),
+            (None, false) => self.matches.back(),
+        };
+        self.current = next.copied();
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_line(read_data: &mut SerialRead, text: &str) {
+        read_data.raw_data.push_back(text.to_string());
+        read_data.timestamps.push_back(Utc::now());
+        read_data.line_counter += 1;
+    }
+
+    #[test]
+    fn test_incremental_search() {
+        let mut read_data = SerialRead::new(10);
+        read_data.start_time = Some(Utc::now());
+        push_line(&mut read_data, "OK boot");
+        push_line(&mut read_data, "ERROR 1");
+
+        let mut search = Search::new();
+        search.query = "error".to_string();
+        search.update(&read_data);
+        assert_eq!(search.match_count(), 1);
+
+        // 新しく届いた行だけを調べる
+        push_line(&mut read_data, "error 2");
+        search.update(&read_data);
+        assert_eq!(search.match_count(), 2);
+        search.filter = SearchFilter::NonMatching;
+        assert_eq!(search.filtered().unwrap(), &[0]);
+
+        // 前後の移動は端で折り返す
+        assert_eq!(search.step(true), Some(1));
+        assert_eq!(search.step(true), Some(2));
+        assert_eq!(search.step(true), Some(1));
+        assert_eq!(search.step(false), Some(2));
+        assert_eq!(search.current_index(), Some(2));
+
+        // 大文字と小文字を区別すると調べ直す
+        search.case_sensitive = true;
+        search.update(&read_data);
+        assert_eq!(search.match_count(), 1);
+        assert_eq!(search.current, None);
+
+        search.regex = true;
+        search.query = "[0-9".to_string();
+        search.update(&read_data);
+        assert!(search.error().is_some());
+        assert_eq!(search.filtered(), None);
+    }
+}
@@ -0,0 +1,226 @@
+// src/frontend/send_history.rs
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// 履歴に残す送信した入力。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredCommand")]
+pub struct SentCommand {
+    /// 入力欄の文字列
+    pub text: String,
+    /// 16進数のバイト列として送ったか
+    pub hex: bool,
+}
+
+impl SentCommand {
+    pub fn new(text: &str, hex: bool) -> Self {
+        Self {
+            text: text.to_string(),
+            hex,
+        }
+    }
+}
+
+/// 設定ファイル上の履歴の1件。以前は文字列だけを保存していたので、その形式も読めるようにする。
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredCommand {
+    Text(String),
+    Command {
+        text: String,
+        #[serde(default)]
+        hex: bool,
+    },
+}
+
+impl From<StoredCommand> for SentCommand {
+    fn from(stored: StoredCommand) -> Self {
+        match stored {
+            StoredCommand::Text(text) => Self { text, hex: false },
+            StoredCommand::Command { text, hex } => Self { text, hex },
+        }
+    }
+}
+
+/// 送信した入力の履歴。新しいものが先頭で、同じ入力は最新の1つだけを残す。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SendHistory {
+    /// 残す件数
+    pub max_len: usize,
+    /// ポートごとに別の履歴を使う
+    pub per_port: bool,
+    /// 全ポート共通の履歴
+    pub commands: Vec<SentCommand>,
+    /// ポート名ごとの履歴
+    pub ports: BTreeMap<String, Vec<SentCommand>>,
+}
+
+impl Default for SendHistory {
+    fn default() -> Self {
+        Self {
+            max_len: 50,
+            per_port: false,
+            commands: Vec::new(),
+            ports: BTreeMap::new(),
+        }
+    }
+}
+
+impl SendHistory {
+    /// `port`を選んでいるときに使う履歴。
+    pub fn commands(&self, port: Option<&str>) -> &[SentCommand] {
+        match port.filter(|_| self.per_port) {
+            Some(port) => self.ports.get(port).map_or(&[], Vec::as_slice),
+            None => &self.commands,
+        }
+    }
+
+    fn commands_mut(&mut self, port: Option<&str>) -> &mut Vec<SentCommand> {
+        match port.filter(|_| self.per_port) {
+            Some(port) => self.ports.entry(port.to_string()).or_default(),
+            None => &mut self.commands,
+        }
+    }
+
+    /// 送信した入力を先頭に加える。文字列と16進数かどうかが同じものが既にあれば先頭に移す。
+    pub fn push(&mut self, port: Option<&str>, command: SentCommand) {
+        let max_len = self.max_len;
+        let commands = self.commands_mut(port);
+        commands.retain(|c| *c != command);
+        commands.insert(0, command);
+        commands.truncate(max_len);
+    }
+
+    /// `port`を選んでいるときに使う履歴を消す。
+    pub fn clear(&mut self, port: Option<&str>) {
+        self.commands_mut(port).clear();
+    }
+
+    /// 残す件数を変えたら、全ての履歴を切り詰める。
+    pub fn truncate(&mut self) {
+        self.commands.truncate(self.max_len);
+        for commands in self.ports.values_mut() {
+            commands.truncate(self.max_len);
+        }
+    }
+}
+
+/// 上下キーで履歴をたどっている位置。
+#[derive(Debug, Default)]
+pub struct HistoryCursor {
+    /// 表示している履歴の添字。`None`なら入力中の文字列
+    index: Option<usize>,
+    /// 履歴をたどり始める前に入力していた文字列と16進数の設定
+    draft: Option<SentCommand>,
+}
+
+impl HistoryCursor {
+    /// 1つ古い（`older`が偽なら新しい）履歴を`text`に入れ、16進数の設定`hex`も戻す。
+    /// `text`を変えたら`true`を返す。
+    pub fn step(
+        &mut self,
+        commands: &[SentCommand],
+        text: &mut String,
+        hex: &mut bool,
+        older: bool,
+    ) -> bool {
+        let next = match (self.index, older) {
+            (None, true) if !commands.is_empty() => Some(0),
+            (Some(index), true) if index + 1 < commands.len() => Some(index + 1),
+            (Some(0), false) => None,
+            (Some(index), false) => Some(index - 1),
+            _ => return false,
+        };
+        if self.index.is_none() {
+            self.draft = Some(SentCommand::new(text, *hex));
+        }
+        self.index = next;
+        let command = match next {
+            Some(index) => commands[index].clone(),
+            None => self
+                .draft
+                .take()
+                .unwrap_or_else(|| SentCommand::new("", *hex)),
+        };
+        *text = command.text;
+        *hex = command.hex;
+        true
+    }
+
+    /// 入力を編集したり送信したりしたら、入力中の文字列に戻る。
+    pub fn reset(&mut self) {
+        self.index = None;
+        self.draft = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = SendHistory {
+            max_len: 3,
+            ..Default::default()
+        };
+        let texts = |commands: &[SentCommand]| -> Vec<String> {
+            commands.iter().map(|c| c.text.clone()).collect()
+        };
+        for command in ["reset", "calib", "reset", "stream on", "01 02"] {
+            history.push(Some("COM3"), SentCommand::new(command, false));
+        }
+        // 重複は最新の1つだけを残し、古いものから捨てる
+        assert_eq!(
+            texts(history.commands(None)),
+            ["01 02", "stream on", "reset"]
+        );
+        // 同じ文字列でも16進数で送ったものは別の入力として残す
+        history.push(Some("COM3"), SentCommand::new("01 02", true));
+        assert_eq!(
+            texts(history.commands(None)),
+            ["01 02", "01 02", "stream on"]
+        );
+
+        history.per_port = true;
+        history.push(Some("COM3"), SentCommand::new("ver", false));
+        assert_eq!(texts(history.commands(Some("COM3"))), ["ver"]);
+        assert!(history.commands(Some("COM4")).is_empty());
+
+        // 呼び出すと16進数の設定も戻り、入力中の文字列に戻れば元の設定になる
+        let commands = history.commands(None).to_vec();
+        let mut cursor = HistoryCursor::default();
+        let mut text = "dra".to_string();
+        let mut hex = false;
+        assert!(cursor.step(&commands, &mut text, &mut hex, true));
+        assert_eq!((text.as_str(), hex), ("01 02", true));
+        assert!(cursor.step(&commands, &mut text, &mut hex, true));
+        assert_eq!((text.as_str(), hex), ("01 02", false));
+        assert!(cursor.step(&commands, &mut text, &mut hex, true));
+        assert_eq!(text, "stream on");
+        assert!(cursor.step(&commands, &mut text, &mut hex, false));
+        assert!(cursor.step(&commands, &mut text, &mut hex, false));
+        assert!(hex);
+        assert!(cursor.step(&commands, &mut text, &mut hex, false));
+        assert_eq!((text.as_str(), hex), ("dra", false));
+        assert!(!cursor.step(&commands, &mut text, &mut hex, false));
+    }
+
+    #[test]
+    fn test_reads_plain_string_entries() {
+        // 16進数の設定を保存する前の設定ファイルの履歴は文字列だけ
+        let history: SendHistory =
+            serde_json::from_str(r#"{"commands": ["help", {"text": "01 A0", "hex": true}]}"#)
+                .unwrap();
+        assert_eq!(
+            history.commands,
+            [
+                SentCommand::new("help", false),
+                SentCommand::new("01 A0", true)
+            ]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+// src/frontend/send_options.rs
+
+use serde::{Deserialize, Serialize};
+
+/// 送信する文字列の末尾に付ける改行コード。
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LineEnding {
+    None,
+    Lf,
+    Cr,
+    CrLf,
+}
+
+impl LineEnding {
+    pub const ALL: [LineEnding; 4] = [
+        LineEnding::None,
+        LineEnding::Lf,
+        LineEnding::Cr,
+        LineEnding::CrLf,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineEnding::None => "No line ending",
+            LineEnding::Lf => "LF",
+            LineEnding::Cr => "CR",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    pub fn bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::None => b"",
+            LineEnding::Lf => b"\n",
+            LineEnding::Cr => b"\r",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// 文字列を送信するときの設定。
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SendOptions {
+    pub line_ending: LineEnding,
+    /// 入力を`01 A0 FF`のような16進数のバイト列として送信する。改行コードは付けない
+    pub hex: bool,
+    /// `\n`や`\x1B`などのエスケープを解釈する
+    pub interpret_escapes: bool,
+    /// 入力欄でEnterを押したら送信する
+    pub enter_to_send: bool,
+    /// 送信したデータをモニタに表示する
+    pub echo: bool,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            hex: false,
+            interpret_escapes: false,
+            enter_to_send: true,
+            echo: false,
+        }
+    }
+}
+
+impl SendOptions {
+    /// 入力された文字列を送信するバイト列にする。エスケープが不正ならエラーメッセージを返す。
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        if self.hex {
+            return parse_hex(text);
+        }
+        let mut bytes = if self.interpret_escapes {
+            unescape(text)?
+        } else {
+            text.as_bytes().to_vec()
+        };
+        bytes.extend_from_slice(self.line_ending.bytes());
+        Ok(bytes)
+    }
+}
+
+/// `01 A0 FF`や`0x01,0xA0`の形式の16進数をバイト列にする。
+/// 空白かカンマで区切った各部分は`0x`を付けてもよく、区切らずに複数バイトを続けてもよい。
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for token in text
+        .split([' ', ',', '\t'])
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid hex byte \"{token}\""));
+        }
+        // `0x1`のような1桁は1バイトとみなす
+        if digits.len() == 1 {
+            bytes.push(u8::from_str_radix(digits, 16).unwrap_or_default());
+            continue;
+        }
+        if digits.len() % 2 != 0 {
+            return Err(format!("Odd number of hex digits in \"{token}\""));
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            bytes.push(u8::from_str_radix(pair, 16).unwrap_or_default());
+        }
+    }
+    Ok(bytes)
+}
+
+/// `\n` `\r` `\t` `\0` `\\` と`\xHH`（1バイト）を解釈する。
+pub fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let byte = (digits.len() == 2)
+                    .then(|| u8::from_str_radix(&digits, 16).ok())
+                    .flatten()
+                    .ok_or_else(|| {
+                        format!("Invalid escape \\x{digits}: expected two hex digits")
+                    })?;
+                bytes.push(byte);
+            }
+            Some(other) => return Err(format!("Unknown escape \\{other}")),
+            None => return Err("Trailing backslash".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let mut options = SendOptions {
+            line_ending: LineEnding::CrLf,
+            hex: false,
+            interpret_escapes: false,
+            enter_to_send: true,
+            echo: false,
+        };
+        assert_eq!(options.encode(r"AT\r").unwrap(), b"AT\\r\r\n");
+
+        options.interpret_escapes = true;
+        options.line_ending = LineEnding::None;
+        assert_eq!(
+            options.encode(r"\x1B[2J\t\\é\xff").unwrap(),
+            b"\x1b[2J\t\\\xc3\xa9\xff"
+        );
+        assert!(options.encode(r"\x1").is_err());
+        assert!(options.encode(r"\q").is_err());
+        assert!(options.encode("end\\").is_err());
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("01 A0 ff").unwrap(), [0x01, 0xa0, 0xff]);
+        assert_eq!(parse_hex("0x01,0xA0, 0x5").unwrap(), [0x01, 0xa0, 0x05]);
+        assert_eq!(parse_hex("01A0FF").unwrap(), [0x01, 0xa0, 0xff]);
+        assert!(parse_hex("  ").unwrap().is_empty());
+        assert!(parse_hex("0A1").is_err());
+        assert!(parse_hex("0xG1").is_err());
+        assert!(parse_hex("0x").is_err());
+    }
+}
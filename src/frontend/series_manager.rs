@@ -0,0 +1,289 @@
+// src/frontend/series_manager.rs
+
+use crossbeam::channel::Sender;
+use eframe::egui;
+
+use super::{ERROR_COLOR, SERIES_COLORS};
+use crate::shared::Event;
+use crate::shared::expression::{self, Expr, SeriesRef};
+use crate::shared::filter::{FilterKind, SignalFilter};
+use crate::shared::serial_read::{DerivedKind, DerivedSeries, SerialRead};
+
+/// 選択できるフィルタ。パラメータは既定値
+const FILTER_KINDS: [FilterKind; 6] = [
+    FilterKind::MovingAverage { window: 10 },
+    FilterKind::Ema { alpha: 0.1 },
+    FilterKind::LowPass { cutoff: 1.0 },
+    FilterKind::Median { window: 5 },
+    FilterKind::Derivative,
+    FilterKind::Integral,
+];
+
+/// 新しく追加する派生系列の種類
+enum NewSeries {
+    Expression(String),
+    Filter { input: usize, kind: FilterKind },
+}
+
+/// 系列の一覧と派生系列（計算式・フィルタ）の追加・削除を行うウィンドウ。
+pub struct SeriesManager {
+    pub open: bool,
+    name: String,
+    new_series: NewSeries,
+}
+
+impl SeriesManager {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            name: String::new(),
+            new_series: NewSeries::Expression(String::new()),
+        }
+    }
+
+    /// 入力中の派生系列を検証する。
+    fn validate(&self, serial_read: &SerialRead) -> Result<DerivedSeries, String> {
+        let name = self.name.trim();
+        if !expression::is_identifier(name) {
+            return Err(
+                "Name must start with a letter and contain only letters, digits and '_'"
+                    .to_string(),
+            );
+        }
+        if expression::is_reserved_name(name) {
+            return Err(format!("'{name}' is a reserved name"));
+        }
+        if serial_read.derived_series.iter().any(|s| s.name == name) {
+            return Err(format!("'{name}' already exists"));
+        }
+
+        let kind = match &self.new_series {
+            NewSeries::Expression(source) => {
+                let expression = Expr::parse(source).map_err(|e| e.to_string())?;
+                // 派生系列は番号がずれることがあるので、番号では受信データの系列だけを参照できる
+                for series in expression.series_refs() {
+                    if let SeriesRef::Index(index) = series
+                        && *index >= serial_read.raw_series_count
+                    {
+                        return Err(format!(
+                            "${} is not a received column; refer to derived series by name",
+                            index + 1
+                        ));
+                    }
+                }
+                DerivedKind::Expression {
+                    source: source.trim().to_string(),
+                    expression,
+                }
+            }
+            NewSeries::Filter { input, kind } => {
+                // 派生系列は番号がずれることがあるので名前で参照する
+                let input = match input.checked_sub(serial_read.raw_series_count) {
+                    Some(derived) => SeriesRef::Name(
+                        serial_read
+                            .derived_series
+                            .get(derived)
+                            .ok_or("Input series does not exist")?
+                            .name
+                            .clone(),
+                    ),
+                    None => SeriesRef::Index(*input),
+                };
+                DerivedKind::Filter {
+                    input,
+                    filter: SignalFilter::new(*kind),
+                }
+            }
+        };
+
+        Ok(DerivedSeries {
+            name: name.to_string(),
+            kind,
+        })
+    }
+
+    fn series_list(
+        &mut self,
+        serial_read: &SerialRead,
+        event_sender: &Sender<Event>,
+        ui: &mut egui::Ui,
+    ) {
+        egui::Grid::new("series list").striped(true).show(ui, |ui| {
+            for i in 0..serial_read.graph_data.len() {
+                // 派生系列は名前で参照する
+                if i < serial_read.raw_series_count {
+                    ui.label(format!("${}", i + 1));
+                } else {
+                    ui.label("");
+                }
+                ui.colored_label(
+                    SERIES_COLORS[i % SERIES_COLORS.len()],
+                    serial_read.series_name(i),
+                );
+                match i.checked_sub(serial_read.raw_series_count) {
+                    Some(derived) => {
+                        ui.monospace(serial_read.derived_series[derived].description());
+                    }
+                    None => {
+                        ui.label("received");
+                    }
+                }
+
+                if ui.button("Filter").clicked() {
+                    self.new_series = NewSeries::Filter {
+                        input: i,
+                        kind: FILTER_KINDS[0],
+                    };
+                    self.name = format!("{}_filtered", serial_read.series_name(i).replace(' ', ""));
+                }
+
+                if let Some(derived) = i.checked_sub(serial_read.raw_series_count)
+                    && ui.button("Remove").clicked()
+                {
+                    event_sender
+                        .send(Event::RemoveDerivedSeries(derived))
+                        .expect("Failed to send RemoveDerivedSeries event");
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn filter_parameters(kind: &mut FilterKind, ui: &mut egui::Ui) {
+        egui::ComboBox::from_id_salt("filter kind")
+            .selected_text(kind.label())
+            .show_ui(ui, |ui| {
+                for candidate in FILTER_KINDS {
+                    if ui
+                        .selectable_label(
+                            std::mem::discriminant(kind) == std::mem::discriminant(&candidate),
+                            candidate.label(),
+                        )
+                        .clicked()
+                    {
+                        *kind = candidate;
+                    }
+                }
+            });
+
+        match kind {
+            FilterKind::MovingAverage { window } | FilterKind::Median { window } => {
+                ui.add(
+                    egui::DragValue::new(window)
+                        .range(1..=10_000)
+                        .prefix("Window: "),
+                );
+            }
+            FilterKind::Ema { alpha } => {
+                ui.add(
+                    egui::DragValue::new(alpha)
+                        .range(0.0..=1.0)
+                        .speed(0.01)
+                        .prefix("Alpha: "),
+                );
+            }
+            FilterKind::LowPass { cutoff } => {
+                ui.add(
+                    egui::DragValue::new(cutoff)
+                        .range(0.001..=f64::MAX)
+                        .speed(0.1)
+                        .prefix("Cutoff: ")
+                        .suffix(" Hz"),
+                );
+            }
+            FilterKind::Derivative | FilterKind::Integral => {}
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        serial_read: &SerialRead,
+        event_sender: &Sender<Event>,
+    ) {
+        let mut open = self.open;
+        egui::Window::new("Series")
+            .open(&mut open)
+            .resizable(true)
+            .show(ctx, |ui| {
+                self.series_list(serial_read, event_sender, ui);
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Add:");
+                    let is_expression = matches!(self.new_series, NewSeries::Expression(_));
+                    if ui.selectable_label(is_expression, "Expression").clicked() && !is_expression
+                    {
+                        self.new_series = NewSeries::Expression(String::new());
+                    }
+                    if ui.selectable_label(!is_expression, "Filter").clicked() && is_expression {
+                        self.new_series = NewSeries::Filter {
+                            input: 0,
+                            kind: FILTER_KINDS[0],
+                        };
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut self.name);
+                });
+
+                match &mut self.new_series {
+                    NewSeries::Expression(source) => {
+                        ui.horizontal(|ui| {
+                            ui.label("Expression:");
+                            ui.add(
+                                egui::TextEdit::singleline(source)
+                                    .hint_text("sqrt($1^2 + $2^2)")
+                                    .code_editor(),
+                            );
+                        });
+                    }
+                    NewSeries::Filter { input, kind } => {
+                        ui.horizontal(|ui| {
+                            ui.label("Input:");
+                            egui::ComboBox::from_id_salt("filter input")
+                                .selected_text(serial_read.series_name(*input))
+                                .show_ui(ui, |ui| {
+                                    for i in 0..serial_read.graph_data.len() {
+                                        ui.selectable_value(input, i, serial_read.series_name(i));
+                                    }
+                                });
+                            Self::filter_parameters(kind, ui);
+                        });
+                    }
+                }
+
+                let is_empty = self.name.is_empty()
+                    && matches!(&self.new_series, NewSeries::Expression(s) if s.is_empty());
+                let validated = self.validate(serial_read);
+                let add_button = ui.add_enabled(validated.is_ok(), egui::Button::new("Add"));
+                match validated {
+                    Ok(series) => {
+                        if add_button.clicked() {
+                            event_sender
+                                .send(Event::AddDerivedSeries(series))
+                                .expect("Failed to send AddDerivedSeries event");
+                            self.name.clear();
+                            if let NewSeries::Expression(source) = &mut self.new_series {
+                                source.clear();
+                            }
+                        }
+                    }
+                    // 何も入力していない間はエラーを出さない
+                    Err(error) if !is_empty => {
+                        ui.colored_label(ERROR_COLOR, error);
+                    }
+                    Err(_) => {}
+                }
+
+                ui.add_space(5.0);
+                ui.small("Reference received columns by $number and derived series by name.");
+                ui.small(
+                    "Functions: sqrt abs sin cos tan asin acos atan exp ln log10 floor ceil round atan2 pow min max",
+                );
+            });
+        self.open = open;
+    }
+}
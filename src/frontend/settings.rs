@@ -0,0 +1,83 @@
+// src/frontend/settings.rs
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::highlight::HighlightRule;
+use super::send_history::SendHistory;
+use super::send_options::SendOptions;
+use crate::shared::SharedData;
+
+/// 再起動しても残すユーザー設定。設定ディレクトリにJSONで保存する。
+/// 保存されていない項目は既定値になる。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// シリアルモニタの行の強調表示のルール。先に一致したルールが使われる
+    pub highlight_rules: Vec<HighlightRule>,
+    /// 文字列を送信するときの改行コードなど
+    pub send_options: SendOptions,
+    /// 送信した文字列の履歴
+    pub send_history: SendHistory,
+    /// 使っているマクロのファイル。`None`なら設定ディレクトリの`macros.json`
+    pub macro_file: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            highlight_rules: HighlightRule::defaults(),
+            send_options: SendOptions::default(),
+            send_history: SendHistory::default(),
+            macro_file: None,
+        }
+    }
+}
+
+impl Settings {
+    /// このアプリケーションの設定ディレクトリ。分からなければ`None`。
+    pub fn config_dir() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .or_else(|| std::env::var_os("APPDATA"))
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_dir.join("serial-plotter"))
+    }
+
+    /// 設定ファイルの場所。設定ディレクトリが分からなければ`None`。
+    fn path() -> Option<PathBuf> {
+        Some(Self::config_dir()?.join("settings.json"))
+    }
+
+    /// 設定ファイルを読み込む。まだ保存されていなければ既定値を返す。
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(io::Error::from),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no configuration directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// 保存し、失敗したらエラーを表示する。
+    pub fn save_or_log(&self, shared_data: &SharedData) {
+        if let Err(e) = self.save() {
+            eprintln!("Failed to save settings: {e}");
+            *shared_data.error_log.lock() = format!("Failed to save settings: {e}");
+        }
+    }
+}
@@ -0,0 +1,354 @@
+// src/frontend/spectrum.rs
+
+use std::f64::consts::PI;
+
+use eframe::egui;
+
+use crate::shared::serial_read::SerialRead;
+
+const FFT_SIZES: &[usize] = &[
+    64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const DEFAULT_FFT_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowFunction {
+    const ALL: [WindowFunction; 4] = [
+        WindowFunction::Rectangular,
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+        WindowFunction::Blackman,
+    ];
+
+    /// 長さ`n`の窓の`i`番目の係数
+    fn coefficient(self, i: usize, n: usize) -> f64 {
+        if n < 2 {
+            return 1.0;
+        }
+        let x = 2.0 * PI * i as f64 / (n - 1) as f64;
+        match self {
+            WindowFunction::Rectangular => 1.0,
+            WindowFunction::Hann => 0.5 - 0.5 * x.cos(),
+            WindowFunction::Hamming => 0.54 - 0.46 * x.cos(),
+            WindowFunction::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleRateSource {
+    /// 受信タイムスタンプから推定する
+    Timestamps,
+    /// ユーザーが入力した値を使う
+    Manual,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MagnitudeScale {
+    Linear,
+    Decibel,
+}
+
+/// スペクトル表示の設定。
+pub struct Spectrum {
+    source: usize,
+    size: usize,
+    window: WindowFunction,
+    sample_rate_source: SampleRateSource,
+    manual_sample_rate: f64,
+    scale: MagnitudeScale,
+    remove_dc: bool,
+}
+
+/// 計算済みのスペクトル。
+pub struct SpectrumResult {
+    /// [周波数, 振幅]
+    pub points: Vec<[f64; 2]>,
+    pub sample_rate: f64,
+    /// 直流成分を除いた最大のビン
+    pub peak: Option<[f64; 2]>,
+}
+
+impl Spectrum {
+    pub fn new() -> Self {
+        Self {
+            source: 0,
+            size: DEFAULT_FFT_SIZE,
+            window: WindowFunction::Hann,
+            sample_rate_source: SampleRateSource::Timestamps,
+            manual_sample_rate: 100.0,
+            scale: MagnitudeScale::Linear,
+            remove_dc: true,
+        }
+    }
+
+    pub fn ui(&mut self, serial_read: &SerialRead, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Series:");
+            egui::ComboBox::from_id_salt("spectrum_source")
+                .selected_text(serial_read.series_name(self.source))
+                .show_ui(ui, |ui| {
+                    for i in 0..serial_read.graph_data.len().max(self.source + 1) {
+                        ui.selectable_value(&mut self.source, i, serial_read.series_name(i));
+                    }
+                });
+
+            ui.label("Samples:");
+            egui::ComboBox::from_id_salt("spectrum_size")
+                .selected_text(self.size.to_string())
+                .show_ui(ui, |ui| {
+                    for &size in FFT_SIZES {
+                        ui.selectable_value(&mut self.size, size, size.to_string());
+                    }
+                });
+
+            ui.label("Window:");
+            egui::ComboBox::from_id_salt("spectrum_window")
+                .selected_text(format!("{:?}", self.window))
+                .show_ui(ui, |ui| {
+                    for window in WindowFunction::ALL {
+                        ui.selectable_value(&mut self.window, window, format!("{window:?}"));
+                    }
+                });
+
+            ui.label("Sample rate:");
+            ui.selectable_value(
+                &mut self.sample_rate_source,
+                SampleRateSource::Timestamps,
+                "Auto",
+            );
+            ui.selectable_value(
+                &mut self.sample_rate_source,
+                SampleRateSource::Manual,
+                "Manual",
+            );
+            if self.sample_rate_source == SampleRateSource::Manual {
+                ui.add(
+                    egui::DragValue::new(&mut self.manual_sample_rate)
+                        .range(0.001..=f64::MAX)
+                        .speed(1.0)
+                        .suffix(" Hz"),
+                );
+            }
+
+            ui.separator();
+
+            ui.selectable_value(&mut self.scale, MagnitudeScale::Linear, "Linear");
+            ui.selectable_value(&mut self.scale, MagnitudeScale::Decibel, "dB");
+            ui.checkbox(&mut self.remove_dc, "Remove DC");
+        });
+    }
+
+    /// 選択中の系列の最新`size`サンプルからスペクトルを計算する。
+    pub fn compute(&self, serial_read: &SerialRead) -> Option<SpectrumResult> {
+        let series = serial_read.graph_data.get(self.source)?;
+
+        // 最新から`size`個の有効な値を集める
+        let skip = series.len().saturating_sub(self.size);
+        let (lines, samples): (Vec<usize>, Vec<f64>) = series.iter().skip(skip).unzip();
+        if samples.len() < 2 {
+            return None;
+        }
+        let (first_line, last_line) = (lines[0], lines[lines.len() - 1]);
+
+        let sample_rate = match self.sample_rate_source {
+            SampleRateSource::Manual => self.manual_sample_rate,
+            SampleRateSource::Timestamps => {
+                let newest = serial_read.timestamp(last_line)?;
+                let oldest = serial_read.timestamp(first_line)?;
+                let seconds = (newest - oldest).num_microseconds()? as f64 / 1e6;
+                if seconds <= 0.0 {
+                    return None;
+                }
+                (samples.len() - 1) as f64 / seconds
+            }
+        };
+
+        let magnitudes = magnitude_spectrum(&samples, self.window, self.remove_dc);
+        let fft_len = samples.len().next_power_of_two();
+        let bin_width = sample_rate / fft_len as f64;
+
+        let points: Vec<[f64; 2]> = magnitudes
+            .iter()
+            .enumerate()
+            .map(|(k, &magnitude)| {
+                let y = match self.scale {
+                    MagnitudeScale::Linear => magnitude,
+                    MagnitudeScale::Decibel => 20.0 * magnitude.max(1e-12).log10(),
+                };
+                [k as f64 * bin_width, y]
+            })
+            .collect();
+
+        let peak = points
+            .iter()
+            .skip(1)
+            .copied()
+            .max_by(|a, b| a[1].total_cmp(&b[1]));
+
+        Some(SpectrumResult {
+            points,
+            sample_rate,
+            peak,
+        })
+    }
+
+    pub fn show(&mut self, serial_read: &SerialRead, ui: &mut egui::Ui) {
+        self.ui(serial_read, ui);
+        ui.add_space(5.0);
+
+        let result = self.compute(serial_read);
+
+        if let Some(result) = &result {
+            ui.horizontal(|ui| {
+                ui.label(format!("Sample rate: {:.3} Hz", result.sample_rate));
+                if let Some([frequency, magnitude]) = result.peak {
+                    ui.separator();
+                    ui.label(format!("Peak: {frequency:.3} Hz ({magnitude:.4})"));
+                }
+            });
+        } else {
+            ui.label("Not enough data to compute the spectrum");
+        }
+
+        let y_label = match self.scale {
+            MagnitudeScale::Linear => "Magnitude",
+            MagnitudeScale::Decibel => "Magnitude [dB]",
+        };
+
+        egui_plot::Plot::new("spectrum plot")
+            .x_axis_label("Frequency [Hz]")
+            .y_axis_label(y_label)
+            .show(ui, |plot_ui| {
+                let Some(result) = result else {
+                    return;
+                };
+                plot_ui.line(
+                    egui_plot::Line::new("spectrum", egui_plot::PlotPoints::new(result.points))
+                        .name(serial_read.series_name(self.source))
+                        .color(egui::Color32::from_rgb(100, 200, 100)),
+                );
+
+                if let Some(peak) = result.peak {
+                    let color = egui::Color32::from_rgb(220, 200, 60);
+                    plot_ui.points(
+                        egui_plot::Points::new("peak", vec![peak])
+                            .color(color)
+                            .radius(4.0),
+                    );
+                    plot_ui.text(
+                        egui_plot::Text::new(
+                            "peak label",
+                            egui_plot::PlotPoint::new(peak[0], peak[1]),
+                            format!("{:.3} Hz", peak[0]),
+                        )
+                        .color(color)
+                        .anchor(egui::Align2::LEFT_BOTTOM),
+                    );
+                }
+            });
+    }
+}
+
+/// 片側振幅スペクトルを計算する。長さが2の冪でなければ0で埋める。
+/// 戻り値の長さは`fft_len / 2 + 1`。
+fn magnitude_spectrum(samples: &[f64], window: WindowFunction, remove_dc: bool) -> Vec<f64> {
+    let n = samples.len();
+    let fft_len = n.next_power_of_two();
+    let mean = if remove_dc {
+        samples.iter().sum::<f64>() / n as f64
+    } else {
+        0.0
+    };
+
+    let mut window_sum = 0.0;
+    let mut buffer: Vec<(f64, f64)> = vec![(0.0, 0.0); fft_len];
+    for (i, &sample) in samples.iter().enumerate() {
+        let w = window.coefficient(i, n);
+        window_sum += w;
+        buffer[i] = ((sample - mean) * w, 0.0);
+    }
+
+    fft(&mut buffer);
+
+    // 窓のゲインで正規化し、片側スペクトルとして振幅を2倍する
+    (0..=fft_len / 2)
+        .map(|k| {
+            let (re, im) = buffer[k];
+            let scale = if k == 0 || k == fft_len / 2 { 1.0 } else { 2.0 };
+            (re * re + im * im).sqrt() * scale / window_sum
+        })
+        .collect()
+}
+
+/// 基数2の反復型FFT（インプレース）。`buffer`の長さは2の冪であること。
+fn fft(buffer: &mut [(f64, f64)]) {
+    let n = buffer.len();
+    if n < 2 {
+        return;
+    }
+
+    // ビット反転並べ替え
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buffer.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        let (w_im, w_re) = angle.sin_cos();
+        for start in (0..n).step_by(len) {
+            let (mut cur_re, mut cur_im) = (1.0, 0.0);
+            for k in 0..len / 2 {
+                let (a_re, a_im) = buffer[start + k];
+                let (b_re, b_im) = buffer[start + k + len / 2];
+                let t_re = b_re * cur_re - b_im * cur_im;
+                let t_im = b_re * cur_im + b_im * cur_re;
+                buffer[start + k] = (a_re + t_re, a_im + t_im);
+                buffer[start + k + len / 2] = (a_re - t_re, a_im - t_im);
+                (cur_re, cur_im) = (cur_re * w_re - cur_im * w_im, cur_re * w_im + cur_im * w_re);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magnitude_spectrum_peak() {
+        // 64サンプル中に8周期、振幅2の正弦波
+        let samples: Vec<f64> = (0..64)
+            .map(|i| 2.0 * (2.0 * PI * 8.0 * i as f64 / 64.0).sin() + 1.0)
+            .collect();
+
+        let magnitudes = magnitude_spectrum(&samples, WindowFunction::Rectangular, true);
+        assert_eq!(magnitudes.len(), 33);
+
+        let peak = (1..magnitudes.len())
+            .max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))
+            .unwrap();
+        assert_eq!(peak, 8);
+        assert!((magnitudes[8] - 2.0).abs() < 1e-9);
+        // 直流成分は除去されている
+        assert!(magnitudes[0].abs() < 1e-9);
+    }
+}
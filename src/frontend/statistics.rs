@@ -0,0 +1,132 @@
+// src/frontend/statistics.rs
+
+use eframe::egui;
+
+use super::SERIES_COLORS;
+use crate::shared::serial_read::SerialRead;
+use crate::shared::statistics::RunningStats;
+
+/// 表示中の窓の統計量を、フレームをまたいで保持する。
+/// 新しいデータが届くか、表示する窓が変わるまで再計算しない。
+pub struct StatsCache {
+    /// 集計したときの`(revision, view_end, plot_range)`
+    key: Option<(u64, usize, usize)>,
+    stats: Vec<RunningStats>,
+}
+
+impl StatsCache {
+    pub fn new() -> Self {
+        Self {
+            key: None,
+            stats: Vec::new(),
+        }
+    }
+
+    /// 窓の統計量を返す。キーが前回と同じならキャッシュを使い回す。
+    pub fn update(
+        &mut self,
+        serial_read: &SerialRead,
+        view_end: usize,
+        plot_range: usize,
+    ) -> &[RunningStats] {
+        let key = (serial_read.revision, view_end, plot_range);
+        if self.key != Some(key) {
+            self.stats = window_stats(serial_read, view_end, plot_range);
+            self.key = Some(key);
+        }
+        &self.stats
+    }
+}
+
+/// 表示中の窓（`view_end`で終わる`plot_range`行）について系列ごとの統計量を集計する。
+pub fn window_stats(
+    serial_read: &SerialRead,
+    view_end: usize,
+    plot_range: usize,
+) -> Vec<RunningStats> {
+    let start = view_end
+        .saturating_sub(plot_range)
+        .max(serial_read.first_line());
+
+    serial_read
+        .graph_data
+        .iter()
+        .map(|series| {
+            let mut stats = RunningStats::default();
+            // 古い順に積む。値のない行は欠損として数える
+            let mut samples = series.range(start..view_end).peekable();
+            for line in start..view_end {
+                let value = samples.next_if(|&(l, _)| l == line).map(|(_, value)| value);
+                if let Some(time) = serial_read.timestamp(line) {
+                    stats.push(value, time);
+                }
+            }
+            stats
+        })
+        .collect()
+}
+
+/// 統計量を系列ごとの表として表示する。
+pub fn stats_grid(id: &str, serial_read: &SerialRead, stats: &[RunningStats], ui: &mut egui::Ui) {
+    let format = |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{v:.4}"));
+
+    egui::Grid::new(id).striped(true).show(ui, |ui| {
+        for header in [
+            "Series",
+            "Count",
+            "Missing",
+            "Min",
+            "Max",
+            "Mean",
+            "RMS",
+            "Std dev",
+            "Last",
+            "Rate [Hz]",
+        ] {
+            ui.label(header);
+        }
+        ui.end_row();
+
+        for (i, stats) in stats.iter().enumerate() {
+            let has_values = stats.count > 0;
+            ui.colored_label(
+                SERIES_COLORS[i % SERIES_COLORS.len()],
+                serial_read.series_name(i),
+            );
+            ui.label(stats.count.to_string());
+            ui.label(stats.missing.to_string());
+            ui.label(format(has_values.then_some(stats.min)));
+            ui.label(format(has_values.then_some(stats.max)));
+            ui.label(format(stats.mean()));
+            ui.label(format(stats.rms()));
+            ui.label(format(stats.std_dev()));
+            ui.label(format(stats.last));
+            ui.label(format(stats.sample_rate()));
+            ui.end_row();
+        }
+    });
+}
+
+/// 表示中の窓とセッション全体の統計量を並べて表示する。
+pub fn statistics_panel(
+    cache: &mut StatsCache,
+    serial_read: &SerialRead,
+    view_end: usize,
+    plot_range: usize,
+    ui: &mut egui::Ui,
+) {
+    egui::ScrollArea::both().show(ui, |ui| {
+        ui.heading("Visible window");
+        stats_grid(
+            "visible stats",
+            serial_read,
+            cache.update(serial_read, view_end, plot_range),
+            ui,
+        );
+
+        ui.add_space(10.0);
+
+        ui.heading("Session");
+        stats_grid("session stats", serial_read, &serial_read.session_stats, ui);
+    });
+}
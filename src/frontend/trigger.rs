@@ -0,0 +1,332 @@
+// src/frontend/trigger.rs
+
+use std::ops::Range;
+
+use eframe::egui;
+
+use crate::shared::serial_read::{SerialRead, Series};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerEdge {
+    Rising,
+    Falling,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerMode {
+    /// トリガが見つからなければライブ表示に戻る
+    Auto,
+    /// トリガが見つからなければ直前に捕捉した窓を表示し続ける
+    Normal,
+    /// アームしてから最初のトリガで一度だけ捕捉し、その窓で停止する
+    Single,
+}
+
+/// 描画に使う、見つかったトリガの位置。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriggerEvent {
+    /// トリガ条件を満たした行の行番号
+    pub line: usize,
+    pub level: f64,
+}
+
+/// オシロスコープ風のトリガ設定と状態。
+pub struct Trigger {
+    pub enabled: bool,
+    pub source: usize,
+    pub edge: TriggerEdge,
+    pub level: f64,
+    /// 窓のうちトリガ点より前に割り当てる割合（0.0〜1.0）
+    pub pre_trigger: f64,
+    pub mode: TriggerMode,
+
+    /// 直前に捕捉したトリガ
+    last_event: Option<TriggerEvent>,
+    /// Singleモードでこの行番号以降のトリガのみを受け付ける
+    armed_at: usize,
+    /// Singleモードで捕捉済みかどうか
+    captured: bool,
+}
+
+impl Trigger {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            source: 0,
+            edge: TriggerEdge::Rising,
+            level: 0.0,
+            pre_trigger: 0.5,
+            mode: TriggerMode::Auto,
+            last_event: None,
+            armed_at: 0,
+            captured: false,
+        }
+    }
+
+    /// Singleモードを再アームする。
+    pub fn arm(&mut self, line_counter: usize) {
+        self.armed_at = line_counter;
+        self.captured = false;
+        self.last_event = None;
+    }
+
+    /// 窓の終端（排他的な行番号）を、トリガ点から求める。
+    pub fn view_end(&self, event: TriggerEvent, plot_range: usize) -> usize {
+        let pre = (plot_range as f64 * self.pre_trigger).round() as usize;
+        event.line + (plot_range - pre.min(plot_range))
+    }
+
+    /// 表示すべきトリガを探す。
+    /// `None`ならトリガなしでライブ表示する。
+    /// Singleモードで新たに捕捉した場合は`captured`に`true`を返す。
+    pub fn update(&mut self, serial_read: &SerialRead, plot_range: usize) -> TriggerUpdate {
+        if !self.enabled {
+            return TriggerUpdate::default();
+        }
+        if self.mode == TriggerMode::Single && self.captured {
+            return TriggerUpdate {
+                event: self.last_event,
+                captured: false,
+            };
+        }
+
+        let pre = (plot_range as f64 * self.pre_trigger).round() as usize;
+        let post = plot_range - pre.min(plot_range);
+
+        let found = serial_read.graph_data.get(self.source).and_then(|series| {
+            let line_counter = serial_read.line_counter;
+            match self.mode {
+                // アームしてから最初のトリガを捕捉する
+                TriggerMode::Single => find_first_trigger(
+                    series,
+                    self.armed_at,
+                    line_counter,
+                    self.edge,
+                    self.level,
+                    post,
+                ),
+                TriggerMode::Auto | TriggerMode::Normal => {
+                    find_last_trigger(series, 0, line_counter, self.edge, self.level, post)
+                }
+            }
+        });
+
+        match found {
+            Some(line) => {
+                let event = TriggerEvent {
+                    line,
+                    level: self.level,
+                };
+                self.last_event = Some(event);
+                let captured = self.mode == TriggerMode::Single;
+                self.captured = captured;
+                TriggerUpdate {
+                    event: Some(event),
+                    captured,
+                }
+            }
+            None => TriggerUpdate {
+                event: match self.mode {
+                    TriggerMode::Auto => None,
+                    TriggerMode::Normal | TriggerMode::Single => self.last_event,
+                },
+                captured: false,
+            },
+        }
+    }
+
+    pub fn ui(&mut self, serial_read: &SerialRead, ui: &mut egui::Ui) {
+        let line_counter = serial_read.line_counter;
+        ui.horizontal(|ui| {
+            ui.label("Source:");
+            egui::ComboBox::from_id_salt("trigger_source")
+                .selected_text(serial_read.series_name(self.source))
+                .show_ui(ui, |ui| {
+                    for i in 0..serial_read.graph_data.len().max(self.source + 1) {
+                        ui.selectable_value(&mut self.source, i, serial_read.series_name(i));
+                    }
+                });
+
+            ui.label("Edge:");
+            egui::ComboBox::from_id_salt("trigger_edge")
+                .selected_text(format!("{:?}", self.edge))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.edge, TriggerEdge::Rising, "Rising");
+                    ui.selectable_value(&mut self.edge, TriggerEdge::Falling, "Falling");
+                });
+
+            ui.add(
+                egui::DragValue::new(&mut self.level)
+                    .speed(0.1)
+                    .prefix("Level: "),
+            );
+
+            ui.label("Pre-trigger:");
+            let mut percent = self.pre_trigger * 100.0;
+            if ui
+                .add(egui::Slider::new(&mut percent, 0.0..=100.0).suffix("%"))
+                .changed()
+            {
+                self.pre_trigger = percent / 100.0;
+            }
+
+            ui.label("Mode:");
+            let previous_mode = self.mode;
+            egui::ComboBox::from_id_salt("trigger_mode")
+                .selected_text(format!("{:?}", self.mode))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.mode, TriggerMode::Auto, "Auto");
+                    ui.selectable_value(&mut self.mode, TriggerMode::Normal, "Normal");
+                    ui.selectable_value(&mut self.mode, TriggerMode::Single, "Single");
+                });
+            if self.mode != previous_mode {
+                self.arm(line_counter);
+            }
+
+            if self.mode == TriggerMode::Single && ui.button("Arm").clicked() {
+                self.arm(line_counter);
+            }
+
+            let status = match (self.mode, self.captured, self.last_event) {
+                (TriggerMode::Single, true, _) => "Captured",
+                (TriggerMode::Single, false, _) => "Armed",
+                (_, _, Some(_)) => "Triggered",
+                (_, _, None) => "Waiting",
+            };
+            ui.label(status);
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TriggerUpdate {
+    pub event: Option<TriggerEvent>,
+    /// Singleモードでこのフレームに新しく捕捉した
+    pub captured: bool,
+}
+
+/// トリガ点を探す行番号の範囲。直前の行と比べるため、`min_line`の1行前から含める。
+/// トリガ点の後に`post`行以上のデータがあるものだけを対象とする。
+/// 次に確定する行の行番号が`line_counter`。
+fn search_range(min_line: usize, line_counter: usize, post: usize) -> Option<Range<usize>> {
+    let end = (line_counter + 1).checked_sub(post.max(1))?;
+    Some(min_line.saturating_sub(1)..end)
+}
+
+/// 隣り合う行の組`((行番号, 値), (次の行番号, 値))`のうち、最初にトリガ条件を満たす組の後の行番号。
+fn first_crossing(
+    mut pairs: impl Iterator<Item = ((usize, f64), (usize, f64))>,
+    edge: TriggerEdge,
+    level: f64,
+) -> Option<usize> {
+    pairs.find_map(|((previous_line, previous), (line, current))| {
+        // 両方の行に値がある組だけを調べる
+        let crossed = previous_line + 1 == line
+            && match edge {
+                TriggerEdge::Rising => previous < level && current >= level,
+                TriggerEdge::Falling => previous > level && current <= level,
+            };
+        crossed.then_some(line)
+    })
+}
+
+/// 行番号が`min_line`以降で最も新しいトリガ点の行番号を返す。
+pub fn find_last_trigger(
+    series: &Series,
+    min_line: usize,
+    line_counter: usize,
+    edge: TriggerEdge,
+    level: f64,
+    post: usize,
+) -> Option<usize> {
+    let lines = search_range(min_line, line_counter, post)?;
+    let newer = series.range(lines.clone()).rev();
+    let older = series.range(lines).rev().skip(1);
+    first_crossing(older.zip(newer), edge, level)
+}
+
+/// 行番号が`min_line`以降で最も古いトリガ点の行番号を返す。
+pub fn find_first_trigger(
+    series: &Series,
+    min_line: usize,
+    line_counter: usize,
+    edge: TriggerEdge,
+    level: f64,
+    post: usize,
+) -> Option<usize> {
+    let lines = search_range(min_line, line_counter, post)?;
+    let older = series.range(lines.clone());
+    let newer = series.range(lines).skip(1);
+    first_crossing(older.zip(newer), edge, level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: &[f64]) -> Series {
+        // 古い順に与えた値を行0から並べる
+        let mut series = Series::default();
+        for (line, &value) in values.iter().enumerate() {
+            series.push(line, value);
+        }
+        series
+    }
+
+    #[test]
+    fn test_find_last_trigger() {
+        // 行番号: 0  1  2  3  4  5  6  7
+        let data = series(&[0.0, 2.0, 0.0, 2.0, 2.0, 0.0, 0.0, 2.0]);
+
+        assert_eq!(
+            find_last_trigger(&data, 0, 8, TriggerEdge::Rising, 1.0, 1),
+            Some(7)
+        );
+        // トリガ点以降に3行必要なら行3の立ち上がり
+        assert_eq!(
+            find_last_trigger(&data, 0, 8, TriggerEdge::Rising, 1.0, 3),
+            Some(3)
+        );
+        assert_eq!(
+            find_last_trigger(&data, 0, 8, TriggerEdge::Falling, 1.0, 1),
+            Some(5)
+        );
+        assert_eq!(
+            find_last_trigger(&data, 0, 8, TriggerEdge::Rising, 5.0, 1),
+            None
+        );
+        // `min_line`より前は探さない
+        assert_eq!(
+            find_last_trigger(&data, 6, 8, TriggerEdge::Falling, 1.0, 1),
+            None
+        );
+        assert_eq!(
+            find_first_trigger(&data, 2, 8, TriggerEdge::Rising, 1.0, 1),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_single_captures_first_trigger_after_arming() {
+        let mut serial_read = SerialRead::new(100);
+        // 行1の立ち上がりはアーム前、行3と行5はアーム後
+        serial_read
+            .graph_data
+            .push(series(&[0.0, 2.0, 0.0, 2.0, 0.0, 2.0, 2.0, 2.0]));
+        serial_read.line_counter = 8;
+
+        let mut trigger = Trigger::new();
+        trigger.enabled = true;
+        trigger.level = 1.0;
+        trigger.mode = TriggerMode::Single;
+        trigger.arm(2);
+
+        let update = trigger.update(&serial_read, 2);
+        assert!(update.captured);
+        assert_eq!(update.event.map(|event| event.line), Some(3));
+        // 捕捉した後は新しいトリガがあっても窓を動かさない
+        let update = trigger.update(&serial_read, 2);
+        assert!(!update.captured);
+        assert_eq!(update.event.map(|event| event.line), Some(3));
+    }
+}
@@ -2,7 +2,7 @@ pub mod backend;
 pub mod frontend;
 pub mod shared;
 
-use crate::{frontend::Frontend, shared::SharedData};
+use crate::shared::SharedData;
 
 pub fn start_app(shared_data: SharedData) -> eframe::Result {
     let (event_sender, event_receiver) = crossbeam::channel::bounded(10);
@@ -1,14 +1,26 @@
 use parking_lot::{Mutex, RwLock};
 use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::time::Duration;
 
+pub mod ansi;
+pub mod expression;
+pub mod filter;
+pub mod history;
 pub mod port_info;
 pub mod serial_read;
+pub mod statistics;
+pub mod transfer;
 
 #[derive(Clone, Debug)]
 pub struct SharedData {
     pub read_data: Arc<RwLock<serial_read::SerialRead>>,
     pub port_info: Arc<RwLock<port_info::PortsInfo>>,
     pub error_log: Arc<Mutex<String>>,
+    /// バックエンドで送信を待っているデータの数
+    pub queued_sends: Arc<AtomicUsize>,
+    /// 実行中か、最後に実行したファイル転送の進み具合。フロントエンドが閉じたら`None`
+    pub transfer: Arc<RwLock<Option<transfer::TransferProgress>>>,
 }
 
 impl SharedData {
@@ -17,17 +29,46 @@ impl SharedData {
             read_data: Arc::new(RwLock::new(serial_read::SerialRead::new(max_data_points))),
             port_info: Arc::new(RwLock::new(port_info::PortsInfo::new())),
             error_log: Arc::new(Mutex::new(String::new())),
+            queued_sends: Arc::new(AtomicUsize::new(0)),
+            transfer: Arc::new(RwLock::new(None)),
         }
     }
 }
 
+/// 待ち時間の後に送信するデータ。
+#[derive(Clone, Debug)]
+pub struct QueuedSend {
+    /// 1つ前のデータを送信して（最初のデータなら、それまでに積まれたデータを全て送信して）からの待ち時間
+    pub delay: Duration,
+    pub bytes: Vec<u8>,
+    /// 送信したデータをモニタに表示する
+    pub echo: bool,
+}
+
 // send to backend to change settings
 pub enum Event {
     SelectPort(String),
     SelectBaudRate(u32),
     RefreshAvailablePorts,
-    ChangeMaxDataPoints(usize),
-    SendText(String),
+    ChangeRetention(serial_read::RetentionPolicy),
+    SetDiskHistory(bool),
+    AddDerivedSeries(serial_read::DerivedSeries),
+    RemoveDerivedSeries(usize),
+    /// `echo`が真なら、送信したデータをモニタに表示する
+    SendBytes {
+        bytes: Vec<u8>,
+        echo: bool,
+    },
+    /// 順に待ち時間を置いて送信する。既に送信を待っているデータがあればその後に送る
+    QueueSends(Vec<QueuedSend>),
+    /// 送信を待っているデータを全て取り消す
+    CancelQueuedSends,
+    /// ファイルを少しずつ送信する
+    SendFile(transfer::FileSend),
+    /// ポートを占有し、XMODEMやYMODEMでファイルを送る
+    ModemSend(transfer::ModemSend),
+    /// 実行中のファイル転送を中止する
+    CancelTransfer,
     ClearLog,
     Shutdown,
 }
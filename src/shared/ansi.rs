@@ -0,0 +1,221 @@
+// src/shared/ansi.rs
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+const ESC: char = '\x1b';
+
+/// SGRで指定される色。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnsiColor {
+    /// 0〜7が標準色、8〜15が明るい色、16〜255が256色パレット
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// SGR（`ESC [ ... m`）で指定される文字の装飾。
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnsiStyle {
+    pub foreground: Option<AnsiColor>,
+    pub background: Option<AnsiColor>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl AnsiStyle {
+    /// SGRのパラメータを順に適用する。
+    fn apply(&mut self, params: &str) {
+        let mut params = params
+            .split([';', ':'])
+            .map(|param| param.parse::<u16>().unwrap_or(0));
+        // パラメータのない`ESC [ m`はリセット
+        let mut next = params.next();
+        if next.is_none() {
+            *self = Self::default();
+        }
+        while let Some(param) = next {
+            match param {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.foreground = Some(AnsiColor::Indexed((param - 30) as u8)),
+                38 => self.foreground = Self::extended_color(&mut params),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(AnsiColor::Indexed((param - 40) as u8)),
+                48 => self.background = Self::extended_color(&mut params),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(AnsiColor::Indexed((param - 90 + 8) as u8)),
+                100..=107 => self.background = Some(AnsiColor::Indexed((param - 100 + 8) as u8)),
+                _ => {}
+            }
+            next = params.next();
+        }
+    }
+
+    /// `38;5;n`（256色）と`38;2;r;g;b`（RGB）の色を読む。
+    fn extended_color(params: &mut impl Iterator<Item = u16>) -> Option<AnsiColor> {
+        let mut component = || params.next().map(|value| value.min(255) as u8);
+        match component() {
+            Some(5) => component().map(AnsiColor::Indexed),
+            Some(2) => Some(AnsiColor::Rgb(component()?, component()?, component()?)),
+            _ => None,
+        }
+    }
+}
+
+/// エスケープシーケンスを取り除いた文字列と、その中の装飾された範囲。
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StyledText {
+    pub text: String,
+    /// (`text`のバイト範囲, 装飾)。装飾のない部分は含まない
+    pub spans: Vec<(Range<usize>, AnsiStyle)>,
+}
+
+/// 1行を解析し、SGRを装飾に変換する。カーソル移動など他のエスケープシーケンスは取り除く。
+/// 装飾は行ごとにリセットされる。
+pub fn parse(line: &str) -> StyledText {
+    let mut styled = StyledText::default();
+    let mut style = AnsiStyle::default();
+    let mut span_start = 0;
+
+    let mut rest = line;
+    while let Some(escape) = rest.find(ESC) {
+        styled.text.push_str(&rest[..escape]);
+        let (sequence, after) = split_sequence(&rest[escape..]);
+        rest = after;
+
+        if let Some(params) = sequence
+            .strip_prefix("\x1b[")
+            .and_then(|sequence| sequence.strip_suffix('m'))
+        {
+            let mut new_style = style;
+            new_style.apply(params);
+            if new_style != style {
+                push_span(&mut styled, span_start, style);
+                span_start = styled.text.len();
+                style = new_style;
+            }
+        }
+    }
+    styled.text.push_str(rest);
+    push_span(&mut styled, span_start, style);
+    styled
+}
+
+fn push_span(styled: &mut StyledText, start: usize, style: AnsiStyle) {
+    if style != AnsiStyle::default() && start < styled.text.len() {
+        styled.spans.push((start..styled.text.len(), style));
+    }
+}
+
+/// エスケープシーケンスを全て取り除く。含まれていなければそのまま返す。
+pub fn strip(line: &str) -> Cow<'_, str> {
+    if !line.contains(ESC) {
+        return Cow::Borrowed(line);
+    }
+    let mut stripped = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(escape) = rest.find(ESC) {
+        stripped.push_str(&rest[..escape]);
+        rest = split_sequence(&rest[escape..]).1;
+    }
+    stripped.push_str(rest);
+    Cow::Owned(stripped)
+}
+
+/// `\r`で行頭に戻って書き直された行を、最後に書かれた内容だけにする。
+/// 改行コードのCRLFによる末尾の`\r`は書き直しとみなさない。
+pub fn rewrite_carriage_returns(line: &str) -> &str {
+    let trimmed = line.trim_end_matches('\r');
+    match trimmed.rfind('\r') {
+        Some(position) => &trimmed[position + 1..],
+        None => line,
+    }
+}
+
+/// ESCで始まる`text`を、先頭のエスケープシーケンスとその後ろに分ける。
+/// 途中で切れたシーケンスは最後までをシーケンスとみなす。
+fn split_sequence(text: &str) -> (&str, &str) {
+    let bytes = text.as_bytes();
+    let end = match bytes.get(1) {
+        // CSI: パラメータと中間バイトの後に0x40〜0x7Eの終端バイト
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|byte| (0x40..=0x7e).contains(byte))
+            .map_or(bytes.len(), |position| position + 3),
+        // OSC: BELかESC \で終わる
+        Some(b']') => {
+            let body = &text[2..];
+            match (body.find('\x07'), body.find("\x1b\\")) {
+                (Some(bel), Some(st)) if st < bel => st + 4,
+                (Some(bel), _) => bel + 3,
+                (None, Some(st)) => st + 4,
+                (None, None) => bytes.len(),
+            }
+        }
+        // その他の2文字のシーケンス
+        Some(_) => 1 + text[1..].chars().next().map_or(0, char::len_utf8),
+        None => 1,
+    };
+    text.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sgr() {
+        let styled = parse("\x1b[1;31mE (12)\x1b[0m ok \x1b[38;5;208mx\x1b[38;2;1;2;3my\x1b[m");
+        assert_eq!(styled.text, "E (12) ok xy");
+        assert_eq!(
+            styled.spans,
+            vec![
+                (
+                    0..6,
+                    AnsiStyle {
+                        foreground: Some(AnsiColor::Indexed(1)),
+                        bold: true,
+                        ..Default::default()
+                    }
+                ),
+                (
+                    10..11,
+                    AnsiStyle {
+                        foreground: Some(AnsiColor::Indexed(208)),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    11..12,
+                    AnsiStyle {
+                        foreground: Some(AnsiColor::Rgb(1, 2, 3)),
+                        ..Default::default()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip() {
+        // カーソル移動、行の消去、OSCのタイトル設定は取り除く
+        assert_eq!(
+            strip("\x1b[2K\x1b[1G\x1b]0;title\x07uart:~$ \x1b[32m1,2\x1b[0m"),
+            "uart:~$ 1,2"
+        );
+        assert_eq!(strip("\x1b[3"), "");
+        assert!(matches!(strip("1,2,3"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_rewrite_carriage_returns() {
+        assert_eq!(rewrite_carriage_returns("10%\r50%\r100%\r"), "100%");
+        assert_eq!(rewrite_carriage_returns("done\r"), "done\r");
+    }
+}
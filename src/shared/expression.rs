@@ -0,0 +1,437 @@
+// src/shared/expression.rs
+
+use std::fmt;
+
+/// 派生系列の計算式。
+///
+/// 使える要素:
+/// - 数値リテラル、定数 `pi`, `e`
+/// - 系列の参照: 受信データの系列は`$1`（1始まりの番号。凡例の"Series 1"に対応）、
+///   派生系列は名前。派生系列の位置は受信データの系列が増えるとずれるので番号では参照しない
+/// - 演算子: `+ - * / % ^`（`^`は右結合のべき乗）、単項`-`、括弧
+/// - 関数: `sqrt abs sin cos tan asin acos atan exp ln log10 floor ceil round`（1引数）、
+///   `atan2 pow min max`（2引数）
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Series(SeriesRef),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SeriesRef {
+    /// 受信データの0始まりの系列番号
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Sqrt,
+    Abs,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Exp,
+    Ln,
+    Log10,
+    Floor,
+    Ceil,
+    Round,
+    Atan2,
+    Pow,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "log10" => Function::Log10,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "round" => Function::Round,
+            "atan2" => Function::Atan2,
+            "pow" => Function::Pow,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => return None,
+        })
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Function::Atan2 | Function::Pow | Function::Min | Function::Max => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Function::Sqrt => args[0].sqrt(),
+            Function::Abs => args[0].abs(),
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Tan => args[0].tan(),
+            Function::Asin => args[0].asin(),
+            Function::Acos => args[0].acos(),
+            Function::Atan => args[0].atan(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Log10 => args[0].log10(),
+            Function::Floor => args[0].floor(),
+            Function::Ceil => args[0].ceil(),
+            Function::Round => args[0].round(),
+            Function::Atan2 => args[0].atan2(args[1]),
+            Function::Pow => args[0].powf(args[1]),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+        }
+    }
+}
+
+/// 名前が関数名や定数と衝突しないかどうか。派生系列の名前の検証に使う。
+pub fn is_reserved_name(name: &str) -> bool {
+    Function::from_name(name).is_some() || matches!(name, "pi" | "e")
+}
+
+/// 系列の名前として参照できる識別子かどうか。
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// エラーが見つかった位置（バイト単位）
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Expr, ParseError> {
+        let mut parser = Parser {
+            source,
+            position: 0,
+        };
+        let expr = parser.expression()?;
+        parser.skip_whitespace();
+        if parser.position < source.len() {
+            return Err(parser.error("Unexpected character"));
+        }
+        Ok(expr)
+    }
+
+    /// 式が参照している系列を出現順に返す。
+    pub fn series_refs(&self) -> Vec<&SeriesRef> {
+        match self {
+            Expr::Number(_) => Vec::new(),
+            Expr::Series(series) => vec![series],
+            Expr::Negate(expr) => expr.series_refs(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut refs = lhs.series_refs();
+                refs.extend(rhs.series_refs());
+                refs
+            }
+            Expr::Call(_, args) => args.iter().flat_map(Expr::series_refs).collect(),
+        }
+    }
+
+    /// 式を評価する。参照した系列の値が欠けている場合や、結果が有限でない場合は`None`。
+    pub fn evaluate(&self, resolve: &impl Fn(&SeriesRef) -> Option<f64>) -> Option<f64> {
+        let value = match self {
+            Expr::Number(value) => *value,
+            Expr::Series(series) => resolve(series)?,
+            Expr::Negate(expr) => -expr.evaluate(resolve)?,
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.evaluate(resolve)?;
+                let rhs = rhs.evaluate(resolve)?;
+                match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Sub => lhs - rhs,
+                    BinaryOp::Mul => lhs * rhs,
+                    BinaryOp::Div => lhs / rhs,
+                    BinaryOp::Rem => lhs % rhs,
+                    BinaryOp::Pow => lhs.powf(rhs),
+                }
+            }
+            Expr::Call(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(resolve))
+                    .collect::<Option<Vec<_>>>()?;
+                function.apply(&args)
+            }
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+/// 再帰下降パーサ
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            position: self.position,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.source[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &str {
+        let start = self.position;
+        let len = self
+            .rest()
+            .find(|c| !predicate(c))
+            .unwrap_or(self.rest().len());
+        self.position += len;
+        &self.source[start..start + len]
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                BinaryOp::Add
+            } else if self.eat('-') {
+                BinaryOp::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    // term := unary (('*' | '/' | '%') unary)*
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                BinaryOp::Mul
+            } else if self.eat('/') {
+                BinaryOp::Div
+            } else if self.eat('%') {
+                BinaryOp::Rem
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat('-') {
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    // power := atom ('^' unary)?
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            let exponent = self.unary()?;
+            Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ))
+        } else {
+            Ok(base)
+        }
+    }
+
+    // atom := number | '$' index | identifier | identifier '(' args ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let expr = self.expression()?;
+                if !self.eat(')') {
+                    return Err(self.error("Expected ')'"));
+                }
+                Ok(expr)
+            }
+            Some('$') => {
+                self.position += 1;
+                let start = self.position;
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                match digits.parse::<usize>() {
+                    Ok(index) if index > 0 => Ok(Expr::Series(SeriesRef::Index(index - 1))),
+                    _ => Err(ParseError {
+                        position: start,
+                        message: "Expected a series number starting from 1".to_string(),
+                    }),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let start = self.position;
+                let mut number = self
+                    .take_while(|c| c.is_ascii_digit() || c == '.')
+                    .to_string();
+                // 指数表記 (1e-3など)
+                if self.rest().starts_with(['e', 'E']) {
+                    let exponent_start = self.position;
+                    self.position += 1;
+                    if self.rest().starts_with(['+', '-']) {
+                        self.position += 1;
+                    }
+                    if self.take_while(|c| c.is_ascii_digit()).is_empty() {
+                        self.position = exponent_start;
+                    } else {
+                        number = self.source[start..self.position].to_string();
+                    }
+                }
+                number.parse().map(Expr::Number).map_err(|_| ParseError {
+                    position: start,
+                    message: "Invalid number".to_string(),
+                })
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let start = self.position;
+                let name = self
+                    .take_while(|c| c.is_ascii_alphanumeric() || c == '_')
+                    .to_string();
+
+                if let Some(function) = Function::from_name(&name) {
+                    if !self.eat('(') {
+                        return Err(self.error("Expected '(' after function name"));
+                    }
+                    let mut args = vec![self.expression()?];
+                    while self.eat(',') {
+                        args.push(self.expression()?);
+                    }
+                    if !self.eat(')') {
+                        return Err(self.error("Expected ')'"));
+                    }
+                    if args.len() != function.arity() {
+                        return Err(ParseError {
+                            position: start,
+                            message: format!(
+                                "Function '{name}' takes {} argument(s)",
+                                function.arity()
+                            ),
+                        });
+                    }
+                    return Ok(Expr::Call(function, args));
+                }
+
+                Ok(match name.as_str() {
+                    "pi" => Expr::Number(std::f64::consts::PI),
+                    "e" => Expr::Number(std::f64::consts::E),
+                    _ => Expr::Series(SeriesRef::Name(name)),
+                })
+            }
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of expression")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str, values: &[f64]) -> Option<f64> {
+        Expr::parse(source)
+            .unwrap()
+            .evaluate(&|series| match series {
+                SeriesRef::Index(i) => values.get(*i).copied(),
+                SeriesRef::Name(name) if name == "rpm" => Some(100.0),
+                SeriesRef::Name(_) => None,
+            })
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("1 + 2 * 3", &[]), Some(7.0));
+        assert_eq!(evaluate("(1 + 2) * 3", &[]), Some(9.0));
+        assert_eq!(evaluate("-2^2", &[]), Some(-4.0));
+        assert_eq!(evaluate("2^3^2", &[]), Some(512.0));
+        assert_eq!(evaluate("1.5e1 - 5", &[]), Some(10.0));
+        assert_eq!(
+            evaluate("sqrt($1^2 + $2^2 + $3^2)", &[2.0, 3.0, 6.0]),
+            Some(7.0)
+        );
+        assert_eq!(evaluate("rpm * 0.5", &[]), Some(50.0));
+        assert_eq!(evaluate("max($1, $2)", &[1.0, 4.0]), Some(4.0));
+        // 参照先の欠損やゼロ除算は値なし
+        assert_eq!(evaluate("$3 + 1", &[1.0]), None);
+        assert_eq!(evaluate("1 / 0", &[]), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Expr::parse("1 +").unwrap_err().position, 3);
+        assert_eq!(Expr::parse("$0").unwrap_err().position, 1);
+        assert!(Expr::parse("sqrt(1, 2)").is_err());
+        assert!(Expr::parse("sqrt 2").is_err());
+        assert!(Expr::parse("(1 + 2").is_err());
+        assert!(Expr::parse("1 2").is_err());
+    }
+}
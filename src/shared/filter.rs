@@ -0,0 +1,241 @@
+// src/shared/filter.rs
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Utc};
+
+/// サンプル間隔の推定に使う、直近の間隔の数
+const INTERVAL_WINDOW: usize = 16;
+
+/// 系列に適用できるフィルタの種類とパラメータ。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterKind {
+    /// 直近`window`個の単純移動平均
+    MovingAverage { window: usize },
+    /// 指数移動平均。`alpha`は新しい値の重み（0.0〜1.0）
+    Ema { alpha: f64 },
+    /// 遮断周波数`cutoff`[Hz]の一次ローパス
+    LowPass { cutoff: f64 },
+    /// 直近`window`個の中央値。スパイク除去に使う
+    Median { window: usize },
+    /// 時間微分 [単位/秒]
+    Derivative,
+    /// 台形則による時間積分 [単位・秒]
+    Integral,
+}
+
+impl FilterKind {
+    pub fn label(&self) -> String {
+        match self {
+            FilterKind::MovingAverage { window } => format!("Moving average ({window})"),
+            FilterKind::Ema { alpha } => format!("EMA (alpha {alpha})"),
+            FilterKind::LowPass { cutoff } => format!("Low-pass ({cutoff} Hz)"),
+            FilterKind::Median { window } => format!("Median ({window})"),
+            FilterKind::Derivative => "Derivative".to_string(),
+            FilterKind::Integral => "Integral".to_string(),
+        }
+    }
+}
+
+/// 1行ずつ値を受け取って出力するフィルタ。内部状態を持つ。
+#[derive(Clone, Debug)]
+pub struct SignalFilter {
+    pub kind: FilterKind,
+
+    /// 移動平均・中央値用の直近の入力
+    window: VecDeque<f64>,
+    /// 直前の出力（EMA・ローパス・積分）または入力（微分）
+    previous: Option<f64>,
+    previous_input: Option<f64>,
+
+    /// サンプル間隔の推定用。複数行がまとめて届くとタイムスタンプが揃ってしまうため、
+    /// タイムスタンプが変わったときに、その前の時刻に届いた行数で差を割って1行あたりの間隔とする。
+    /// 受信が途切れた間の長い間隔に引きずられないよう、直近の間隔の中央値を使う。
+    last_time: Option<DateTime<Utc>>,
+    samples_at_last_time: usize,
+    recent_intervals: VecDeque<f64>,
+    interval: Option<f64>,
+}
+
+impl SignalFilter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            window: VecDeque::new(),
+            previous: None,
+            previous_input: None,
+            last_time: None,
+            samples_at_last_time: 0,
+            recent_intervals: VecDeque::new(),
+            interval: None,
+        }
+    }
+
+    /// 内部状態を捨て、作り直した直後と同じ状態に戻す。
+    pub fn reset(&mut self) {
+        *self = Self::new(self.kind);
+    }
+
+    /// 直近のサンプル間隔 [秒] の推定値を更新する。
+    fn update_interval(&mut self, time: DateTime<Utc>) {
+        let Some(last_time) = self.last_time.filter(|&last| time > last) else {
+            // 最初の行か、前の行と同じ時刻にまとめて届いた行
+            self.last_time.get_or_insert(time);
+            self.samples_at_last_time += 1;
+            return;
+        };
+
+        if let Some(us) = (time - last_time).num_microseconds() {
+            let seconds = us as f64 / 1_000_000.0;
+            self.recent_intervals
+                .push_back(seconds / self.samples_at_last_time as f64);
+            if self.recent_intervals.len() > INTERVAL_WINDOW {
+                self.recent_intervals.pop_front();
+            }
+
+            let mut sorted: Vec<f64> = self.recent_intervals.iter().copied().collect();
+            sorted.sort_by(f64::total_cmp);
+            self.interval = Some(sorted[sorted.len() / 2]);
+        }
+        self.last_time = Some(time);
+        self.samples_at_last_time = 1;
+    }
+
+    /// 値を1つ入力し、フィルタ後の値を返す。欠損値は状態を変えずに欠損として出力する。
+    pub fn apply(&mut self, value: Option<f64>, time: DateTime<Utc>) -> Option<f64> {
+        let value = value?;
+        self.update_interval(time);
+
+        let output = match self.kind {
+            FilterKind::MovingAverage { window } => {
+                self.window.push_back(value);
+                while self.window.len() > window.max(1) {
+                    self.window.pop_front();
+                }
+                Some(self.window.iter().sum::<f64>() / self.window.len() as f64)
+            }
+            FilterKind::Median { window } => {
+                self.window.push_back(value);
+                while self.window.len() > window.max(1) {
+                    self.window.pop_front();
+                }
+                let mut sorted: Vec<f64> = self.window.iter().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                let mid = sorted.len() / 2;
+                Some(if sorted.len().is_multiple_of(2) {
+                    (sorted[mid - 1] + sorted[mid]) / 2.0
+                } else {
+                    sorted[mid]
+                })
+            }
+            FilterKind::Ema { alpha } => {
+                let alpha = alpha.clamp(0.0, 1.0);
+                Some(self.previous.map_or(value, |y| y + alpha * (value - y)))
+            }
+            FilterKind::LowPass { cutoff } => match (self.previous, self.interval) {
+                (Some(y), Some(dt)) => {
+                    let rc = 1.0 / (2.0 * PI * cutoff);
+                    let alpha = dt / (rc + dt);
+                    Some(y + alpha * (value - y))
+                }
+                // 間隔がまだ分からないうちは入力をそのまま出す
+                _ => Some(value),
+            },
+            FilterKind::Derivative => match (self.previous_input, self.interval) {
+                (Some(x), Some(dt)) => Some((value - x) / dt),
+                _ => None,
+            },
+            FilterKind::Integral => match (self.previous, self.previous_input, self.interval) {
+                (Some(sum), Some(x), Some(dt)) => Some(sum + (value + x) / 2.0 * dt),
+                (Some(sum), _, _) => Some(sum),
+                _ => Some(0.0),
+            },
+        };
+
+        self.previous_input = Some(value);
+        self.previous = output;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn run(kind: FilterKind, values: &[f64]) -> Vec<Option<f64>> {
+        let mut filter = SignalFilter::new(kind);
+        let start = Utc::now();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| filter.apply(Some(v), start + Duration::milliseconds(100 * i as i64)))
+            .collect()
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(
+            run(FilterKind::MovingAverage { window: 2 }, &[1.0, 3.0, 5.0]),
+            vec![Some(1.0), Some(2.0), Some(4.0)]
+        );
+        assert_eq!(
+            run(FilterKind::Median { window: 3 }, &[1.0, 100.0, 2.0, 3.0]),
+            vec![Some(1.0), Some(50.5), Some(2.0), Some(3.0)]
+        );
+        assert_eq!(
+            run(FilterKind::Ema { alpha: 0.5 }, &[0.0, 2.0, 2.0]),
+            vec![Some(0.0), Some(1.0), Some(1.5)]
+        );
+        // 0.1秒間隔で1ずつ増える -> 10/秒
+        let derivative = run(FilterKind::Derivative, &[0.0, 1.0, 2.0]);
+        assert_eq!(derivative[0], None);
+        assert!((derivative[2].unwrap() - 10.0).abs() < 1e-9);
+        // 値1を0.2秒積分
+        let integral = run(FilterKind::Integral, &[1.0, 1.0, 1.0]);
+        assert!((integral[2].unwrap() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interval_ignores_pauses_and_batches() {
+        let mut filter = SignalFilter::new(FilterKind::Derivative);
+        let mut time = Utc::now();
+        let mut value = 0.0;
+        for _ in 0..10 {
+            filter.apply(Some(value), time);
+            time += Duration::milliseconds(100);
+            value += 1.0;
+        }
+
+        // 受信が10秒途切れても、その後の間隔は0.1秒のまま推定される
+        time += Duration::seconds(10);
+        filter.apply(Some(value), time);
+        time += Duration::milliseconds(100);
+        value += 1.0;
+        let derivative = filter.apply(Some(value), time).unwrap();
+        assert!((derivative - 10.0).abs() < 1e-9);
+
+        // 0.2秒ごとに2行ずつまとめて届いても、1行あたり0.1秒とみなす
+        for _ in 0..10 {
+            for _ in 0..2 {
+                value += 1.0;
+                filter.apply(Some(value), time);
+            }
+            time += Duration::milliseconds(200);
+        }
+        value += 1.0;
+        let derivative = filter.apply(Some(value), time).unwrap();
+        assert!((derivative - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_missing_values_keep_state() {
+        let mut filter = SignalFilter::new(FilterKind::Ema { alpha: 0.5 });
+        let now = Utc::now();
+        assert_eq!(filter.apply(Some(4.0), now), Some(4.0));
+        assert_eq!(filter.apply(None, now), None);
+        assert_eq!(filter.apply(Some(0.0), now), Some(2.0));
+    }
+}
@@ -0,0 +1,344 @@
+// src/shared/history.rs
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use chrono::{DateTime, Utc};
+
+/// 何行ごとに索引を作るか
+const INDEX_INTERVAL: usize = 1024;
+
+/// 同じプロセス内で複数の履歴ファイルを作ったときに名前が重ならないようにする
+static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// 作り直しやクリアのたびに新しい値を割り当て、古い内容を読み込んだページと区別する
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// 系列の並びが変わっても同じ系列を指す識別子。ディスクに書き出す値に付ける。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeriesId {
+    /// 受信データの0始まりの列番号
+    Raw(usize),
+    /// 派生系列を追加したときに割り当てた番号
+    Derived(u32),
+}
+
+impl SeriesId {
+    /// ファイル上では最上位ビットで派生系列を区別する
+    const DERIVED_BIT: u32 = 1 << 31;
+
+    fn encode(self) -> u32 {
+        match self {
+            SeriesId::Raw(column) => column as u32 & !Self::DERIVED_BIT,
+            SeriesId::Derived(id) => id | Self::DERIVED_BIT,
+        }
+    }
+
+    fn decode(value: u32) -> Self {
+        if value & Self::DERIVED_BIT != 0 {
+            SeriesId::Derived(value & !Self::DERIVED_BIT)
+        } else {
+            SeriesId::Raw(value as usize)
+        }
+    }
+}
+
+/// ディスクから読み込んだ1行分のデータ。
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryLine {
+    pub line: usize,
+    pub time: DateTime<Utc>,
+    /// 行の先頭の、受信したバイト列全体での位置
+    pub offset: u64,
+    /// 受信したバイト列（改行を除く）
+    pub bytes: Vec<u8>,
+    /// `bytes`をUTF-8として読んだ文字列。不正なバイトは置換文字になる
+    pub text: String,
+    /// (系列の識別子, 値)。値のある系列だけを持つ
+    pub values: Vec<(SeriesId, f64)>,
+}
+
+/// メモリから押し出された古い行を書き出す、追記専用の一時ファイル。
+/// 行は行番号の順に隙間なく追記され、`INDEX_INTERVAL`行ごとの索引から任意の範囲を読み出せる。
+///
+/// レコードの形式（リトルエンディアン）:
+/// 行番号 u64, 時刻 i64 [µs], 位置 u64, バイト数 u32, バイト列, 値の数 u32, (系列 u32, 値 f64) × 値の数
+/// 系列は`SeriesId`を符号化したもの
+#[derive(Debug)]
+pub struct DiskHistory {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// `first_line + k * INDEX_INTERVAL`行目のレコードの先頭位置
+    index: Vec<u64>,
+    /// ファイルにある最初の行と、次に追記する行の行番号
+    first_line: usize,
+    end_line: usize,
+    /// 書き込んだバイト数
+    len: u64,
+    /// 内容が作り直されるたびに変わる。読み込んだページの無効化に使う
+    generation: u64,
+    /// 書き込みに失敗したときのエラー。以降の追記は行わない
+    error: Option<io::Error>,
+    failed: bool,
+}
+
+impl DiskHistory {
+    /// 一時ディレクトリに空の履歴ファイルを作る。ファイルはドロップ時に削除される。
+    pub fn create() -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "serial-plotter-{}-{}.history",
+            std::process::id(),
+            FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::create(&path)?;
+
+        Ok(Self {
+            path,
+            writer: BufWriter::new(file),
+            index: Vec::new(),
+            first_line: 0,
+            end_line: 0,
+            len: 0,
+            generation: GENERATION.fetch_add(1, Ordering::Relaxed),
+            error: None,
+            failed: false,
+        })
+    }
+
+    /// ファイルに書き出されている行の行番号の範囲。
+    pub fn lines(&self) -> Range<usize> {
+        self.first_line..self.end_line
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.first_line == self.end_line
+    }
+
+    /// ファイルの大きさ [バイト]。
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// 書き込みで起きたエラーを取り出す。
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    fn fail(&mut self, error: io::Error) {
+        self.failed = true;
+        self.error = Some(error);
+    }
+
+    /// 1行を追記する。`line`は直前に追記した行の次の行番号でなければならない。
+    /// 最初の行は任意の行番号から始められる。
+    pub fn append(
+        &mut self,
+        line: usize,
+        time: DateTime<Utc>,
+        offset: u64,
+        bytes: &[u8],
+        values: &[(SeriesId, f64)],
+    ) {
+        if self.failed {
+            return;
+        }
+        if self.is_empty() {
+            self.first_line = line;
+            self.end_line = line;
+        }
+        debug_assert_eq!(line, self.end_line);
+
+        if (line - self.first_line).is_multiple_of(INDEX_INTERVAL) {
+            self.index.push(self.len);
+        }
+
+        let mut record = Vec::with_capacity(28 + bytes.len() + values.len() * 12);
+        record.extend_from_slice(&(line as u64).to_le_bytes());
+        record.extend_from_slice(&time.timestamp_micros().to_le_bytes());
+        record.extend_from_slice(&offset.to_le_bytes());
+        record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        record.extend_from_slice(bytes);
+        record.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        for &(series, value) in values {
+            record.extend_from_slice(&series.encode().to_le_bytes());
+            record.extend_from_slice(&value.to_le_bytes());
+        }
+
+        match self.writer.write_all(&record) {
+            Ok(()) => {
+                self.len += record.len() as u64;
+                self.end_line = line + 1;
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// 追記した内容をファイルに反映する。読み出す前に呼ぶ必要がある。
+    pub fn flush(&mut self) {
+        if self.failed {
+            return;
+        }
+        if let Err(e) = self.writer.flush() {
+            self.fail(e);
+        }
+    }
+
+    /// 全ての行を捨てて空にする。
+    pub fn reset(&mut self) {
+        self.index.clear();
+        self.first_line = 0;
+        self.end_line = 0;
+        self.len = 0;
+        self.generation = GENERATION.fetch_add(1, Ordering::Relaxed);
+        self.failed = false;
+
+        let result = self.writer.flush().and_then(|()| {
+            let file = self.writer.get_mut();
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0)).map(|_| ())
+        });
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+
+    /// 行番号が`lines`の範囲にある行を読み出す。ファイルにない行は含まれない。
+    pub fn read_lines(&self, lines: Range<usize>) -> io::Result<Vec<HistoryLine>> {
+        let start = lines.start.max(self.first_line);
+        let end = lines.end.min(self.end_line);
+        if start >= end {
+            return Ok(Vec::new());
+        }
+
+        let block = (start - self.first_line) / INDEX_INTERVAL;
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(self.index[block]))?;
+
+        let mut result = Vec::with_capacity(end - start);
+        let mut line = self.first_line + block * INDEX_INTERVAL;
+        while line < end {
+            let record = read_record(&mut reader)?;
+            line = record.line + 1;
+            if record.line >= start {
+                result.push(record);
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl Drop for DiskHistory {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            eprintln!("Failed to remove history file: {e}");
+        }
+    }
+}
+
+fn read_record(reader: &mut impl Read) -> io::Result<HistoryLine> {
+    fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+        let mut buf = [0; N];
+        reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    let line = u64::from_le_bytes(read_array(reader)?) as usize;
+    let micros = i64::from_le_bytes(read_array(reader)?);
+    let time = DateTime::from_timestamp_micros(micros)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid timestamp"))?;
+
+    let offset = u64::from_le_bytes(read_array(reader)?);
+    let byte_count = u32::from_le_bytes(read_array(reader)?) as usize;
+    let mut bytes = vec![0; byte_count];
+    reader.read_exact(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes).into_owned();
+
+    let value_count = u32::from_le_bytes(read_array(reader)?) as usize;
+    let mut values = Vec::with_capacity(value_count);
+    for _ in 0..value_count {
+        let series = SeriesId::decode(u32::from_le_bytes(read_array(reader)?));
+        let value = f64::from_le_bytes(read_array(reader)?);
+        values.push((series, value));
+    }
+
+    Ok(HistoryLine {
+        line,
+        time,
+        offset,
+        bytes,
+        text,
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_read() {
+        let mut history = DiskHistory::create().unwrap();
+        let time = DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap();
+
+        // 索引の区切りをまたぐように書き込む
+        let first = 10;
+        let count = INDEX_INTERVAL * 2 + 100;
+        for line in first..first + count {
+            let text = format!("line {line}");
+            history.append(
+                line,
+                time,
+                line as u64 * 8,
+                text.as_bytes(),
+                &[(SeriesId::Raw(1), line as f64), (SeriesId::Derived(3), 0.5)],
+            );
+        }
+        history.flush();
+        assert!(history.take_error().is_none());
+        assert_eq!(history.lines(), first..first + count);
+
+        let start = first + INDEX_INTERVAL + 5;
+        let lines = history.read_lines(start..start + 3).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            HistoryLine {
+                line: start,
+                time,
+                offset: start as u64 * 8,
+                bytes: format!("line {start}").into_bytes(),
+                text: format!("line {start}"),
+                values: vec![
+                    (SeriesId::Raw(1), start as f64),
+                    (SeriesId::Derived(3), 0.5)
+                ],
+            }
+        );
+        assert_eq!(lines[2].line, start + 2);
+
+        // ファイルにない範囲は切り詰められる
+        assert_eq!(history.read_lines(0..first + 1).unwrap().len(), 1);
+        assert!(
+            history
+                .read_lines(first + count..first + count + 10)
+                .unwrap()
+                .is_empty()
+        );
+
+        history.reset();
+        assert!(history.is_empty());
+        // UTF-8として不正なバイト列もそのまま戻る
+        history.append(0, time, 0, b"again\xff", &[]);
+        history.flush();
+        let line = &history.read_lines(0..1).unwrap()[0];
+        assert_eq!(line.bytes, b"again\xff");
+        assert_eq!(line.text, "again\u{FFFD}");
+    }
+}
@@ -1,54 +1,409 @@
 // src/backend/shared_data.rs
 
 use std::collections::VecDeque;
-use chrono::{DateTime, Utc};
+use std::ops::Range;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::expression::{Expr, SeriesRef};
+use super::filter::SignalFilter;
+use super::history::{DiskHistory, SeriesId};
+use super::statistics::RunningStats;
+
+/// 1行あたりの、文字列の中身以外のおおよそのメモリ使用量（String、タイムスタンプと位置）
+const LINE_OVERHEAD_BYTES: usize =
+    size_of::<String>() + size_of::<DateTime<Utc>>() + size_of::<u64>();
+
+/// 履歴をどこまで保持するか。条件を超えた古い行から捨てる。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RetentionPolicy {
+    /// 最新N行
+    Lines(usize),
+    /// 最新の行から一定時間以内に確定した行
+    Duration(TimeDelta),
+    /// おおよそのメモリ使用量がNメガバイト以内
+    Megabytes(usize),
+}
+
+impl RetentionPolicy {
+    pub fn label(&self) -> String {
+        match self {
+            RetentionPolicy::Lines(lines) => format!("{lines} lines"),
+            RetentionPolicy::Duration(duration) => {
+                let seconds = duration.num_seconds();
+                if seconds >= 60 && seconds % 60 == 0 {
+                    format!("{} min", seconds / 60)
+                } else {
+                    format!("{seconds} s")
+                }
+            }
+            RetentionPolicy::Megabytes(megabytes) => format!("{megabytes} MB"),
+        }
+    }
+}
+
+/// 送信したデータ。モニタでは受信した行の間に表示する。
+#[derive(Clone, Debug)]
+pub struct SentData {
+    /// 送信したときに受信中だった行の行番号。この行の手前に表示する
+    pub line: usize,
+    pub time: DateTime<Utc>,
+    pub bytes: Box<[u8]>,
+}
 
 /// フロントエンドとバックエンドで共有されるデータ全体。
 /// この構造体が Arc<RwLock<...>> でラップされる。
-#[derive(Clone, Debug)]
+///
+/// 各行には起動（またはクリア）からの連番の行番号が振られる。
+/// 行ごとのデータは列ごとにリングバッファ（末尾が最新）で保持し、
+/// 保持している行は`first_line()..line_counter`の範囲になる。
+#[derive(Debug)]
 pub struct SerialRead {
-    /// シリアルモニタ用の生データ。リングバッファとして機能する。
-    /// index 0: 現在受信中の行（書き込み可能）
-    /// index 1..: 確定した過去の行（読み取り専用）
+    /// シリアルモニタ用の確定した行。末尾が最新。
     pub raw_data: VecDeque<String>,
 
+    /// 現在受信中でまだ改行が来ていない行。
+    pub current_line: String,
+
+    /// 受信中の行のバイト列。`current_line`はこれをUTF-8として読んだもの。
+    pub current_bytes: Vec<u8>,
+
+    /// UTF-8として不正なバイトを含む行の(行番号, 受信したバイト列)。行番号の昇順。
+    /// それ以外の行はraw_dataの文字列が受信したバイト列と一致するので保持しない。
+    pub non_utf8_lines: VecDeque<(usize, Box<[u8]>)>,
+
+    /// 各行の先頭の、受信したバイト列全体での位置。raw_dataと同じ並び。
+    pub offsets: VecDeque<u64>,
+
+    /// 起動（またはクリア）してから受信した総バイト数。
+    pub received_bytes: u64,
+
+    /// モニタに表示する送信したデータ。行番号の昇順で、保持している行の範囲のものだけを残す。
+    pub sent: VecDeque<SentData>,
+
     /// シリアルプロッタ用のパース済みデータ。
-    /// 各VecDequeが1つのデータ系列に対応する。
-    /// 全てのVecDequeは常に同じ長さを保ち、矩形を維持する。
-    pub graph_data: Vec<VecDeque<Option<f64>>>,
+    /// 各Seriesが1つのデータ系列に対応し、値のある行だけを保持する。
+    /// 先頭`raw_series_count`個が受信データの系列、その後ろに派生系列が続く。
+    pub graph_data: Vec<Series>,
+
+    /// graph_dataのうち受信データから得た系列の数。
+    pub raw_series_count: usize,
+
+    /// 計算式で定義された派生系列。graph_data[raw_series_count..]に対応する。
+    pub derived_series: Vec<DerivedSeries>,
 
-    /// 各行が確定したときのタイムスタンプ。raw_data[1..]とgraph_dataの各要素に対応する。
+    /// 派生系列ごとの`SeriesId::Derived`の番号。derived_seriesと同じ並び。
+    pub derived_ids: Vec<u32>,
+
+    /// 次に追加する派生系列に割り当てる番号。
+    pub next_derived_id: u32,
+
+    /// 各行が確定したときのタイムスタンプ。raw_dataと同じ並び。
     pub timestamps: VecDeque<DateTime<Utc>>,
 
-    /// 起動してからの総行数カウンタ。X軸の連番として利用する。
+    /// 起動（またはクリア）してから最初の行が確定した時刻。
+    pub start_time: Option<DateTime<Utc>>,
+
+    /// 起動してからの総行数カウンタ。次に確定する行の行番号で、X軸の連番として利用する。
     pub line_counter: usize,
 
-    /// raw_dataとgraph_dataが保持する最大行数。
-    pub max_data_points: usize,
+    /// raw_dataとgraph_dataに保持する履歴の範囲。
+    pub retention: RetentionPolicy,
+
+    /// 保持している行のおおよそのメモリ使用量 [バイト]。
+    pub retained_bytes: usize,
+
+    /// 有効な場合、保持する範囲から押し出された行をディスクに書き出す。
+    pub disk_history: Option<DiskHistory>,
+
+    /// 起動（またはクリア）してからの系列ごとの統計量。graph_dataと同じ並び。
+    /// max_data_pointsによる切り捨ての影響を受けない。
+    pub session_stats: Vec<RunningStats>,
+
+    /// graph_dataが変わるたびに増える世代番号。描画キャッシュの無効化に使う。
+    pub revision: u64,
 }
 
 impl SerialRead {
     pub fn new(max_data_points: usize) -> Self {
-        let mut raw_data = VecDeque::with_capacity(max_data_points);
-        // 初期状態として、書き込み対象の空文字列を一つ入れておく
-        raw_data.push_front(String::new());
-
         Self {
-            raw_data,
+            raw_data: VecDeque::with_capacity(max_data_points),
+            current_line: String::new(),
+            current_bytes: Vec::new(),
+            non_utf8_lines: VecDeque::new(),
+            offsets: VecDeque::with_capacity(max_data_points),
+            received_bytes: 0,
+            sent: VecDeque::new(),
             graph_data: Vec::new(),
+            raw_series_count: 0,
+            derived_series: Vec::new(),
+            derived_ids: Vec::new(),
+            next_derived_id: 0,
             timestamps: VecDeque::with_capacity(max_data_points),
+            start_time: None,
             line_counter: 0,
-            max_data_points,
+            retention: RetentionPolicy::Lines(max_data_points),
+            retained_bytes: 0,
+            disk_history: None,
+            session_stats: Vec::new(),
+            revision: 0,
+        }
+    }
+
+    /// 凡例などに表示する系列の名前。
+    pub fn series_name(&self, index: usize) -> String {
+        match index.checked_sub(self.raw_series_count) {
+            Some(derived) if derived < self.derived_series.len() => {
+                self.derived_series[derived].name.clone()
+            }
+            _ => format!("Series {}", index + 1),
+        }
+    }
+
+    /// graph_data[index]の系列の識別子。受信データの系列が増えたり派生系列が消えたりしても変わらない。
+    pub fn series_id(&self, index: usize) -> SeriesId {
+        match index.checked_sub(self.raw_series_count) {
+            Some(derived) => SeriesId::Derived(self.derived_ids[derived]),
+            None => SeriesId::Raw(index),
+        }
+    }
+
+    /// 保持している最も古い行の行番号。
+    pub fn first_line(&self) -> usize {
+        self.line_counter - self.timestamps.len()
+    }
+
+    /// ディスク上の履歴も含めて、参照できる最も古い行の行番号。
+    pub fn oldest_line(&self) -> usize {
+        match &self.disk_history {
+            Some(history) if !history.is_empty() => history.lines().start,
+            _ => self.first_line(),
+        }
+    }
+
+    /// 行番号`line`の行が確定したときのタイムスタンプ。
+    pub fn timestamp(&self, line: usize) -> Option<DateTime<Utc>> {
+        self.timestamps
+            .get(line.checked_sub(self.first_line())?)
+            .copied()
+    }
+
+    /// 行番号`line`の行の受信したバイト列（改行を除く）。受信中の行も含む。
+    pub fn bytes(&self, line: usize) -> Option<&[u8]> {
+        if line == self.line_counter {
+            return Some(&self.current_bytes);
+        }
+        let text = self.raw_data.get(line.checked_sub(self.first_line())?)?;
+        match self
+            .non_utf8_lines
+            .binary_search_by_key(&line, |&(line, _)| line)
+        {
+            Ok(index) => Some(&self.non_utf8_lines[index].1),
+            Err(_) => Some(text.as_bytes()),
+        }
+    }
+
+    /// 行番号`line`の行の先頭の、受信したバイト列全体での位置。受信中の行も含む。
+    pub fn offset(&self, line: usize) -> Option<u64> {
+        if line == self.line_counter {
+            return Some(self.received_bytes - self.current_bytes.len() as u64);
+        }
+        self.offsets
+            .get(line.checked_sub(self.first_line())?)
+            .copied()
+    }
+
+    /// 確定した1行が使うおおよそのメモリ量 [バイト]。系列の値は含まない。
+    pub fn line_bytes(line: &str) -> usize {
+        line.len() + LINE_OVERHEAD_BYTES
+    }
+
+    /// UTF-8として不正な行のバイト列を保持するのに使うおおよそのメモリ量 [バイト]。
+    pub fn non_utf8_bytes(bytes: &[u8]) -> usize {
+        bytes.len() + size_of::<(usize, Box<[u8]>)>()
+    }
+
+    /// 送信したデータを保持するのに使うおおよそのメモリ量 [バイト]。
+    pub fn sent_bytes(bytes: &[u8]) -> usize {
+        bytes.len() + size_of::<SentData>()
+    }
+
+    pub fn change_retention(&mut self, retention: RetentionPolicy) {
+        self.retention = retention;
+        self.revision += 1;
+        self.apply_retention();
+    }
+
+    /// 保持する範囲を超えた古い行を捨てる。新しい行は残る。
+    /// ディスクへの書き出しが有効なら、捨てる行はファイルに追記する。
+    pub fn apply_retention(&mut self) {
+        let mut spilled = false;
+        while self.exceeds_retention() {
+            let line = self.first_line();
+            let (Some(text), Some(time), Some(offset)) = (
+                self.raw_data.pop_front(),
+                self.timestamps.pop_front(),
+                self.offsets.pop_front(),
+            ) else {
+                break;
+            };
+            self.retained_bytes = self.retained_bytes.saturating_sub(Self::line_bytes(&text));
+            let non_utf8 = match self.non_utf8_lines.front() {
+                Some(&(l, _)) if l == line => self.non_utf8_lines.pop_front().map(|(_, b)| b),
+                _ => None,
+            };
+            if let Some(bytes) = &non_utf8 {
+                self.retained_bytes = self
+                    .retained_bytes
+                    .saturating_sub(Self::non_utf8_bytes(bytes));
+            }
+            // 捨てる行の手前に送信したデータも捨てる
+            while let Some(sent) = self.sent.pop_front_if(|sent| sent.line <= line) {
+                self.retained_bytes = self
+                    .retained_bytes
+                    .saturating_sub(Self::sent_bytes(&sent.bytes));
+            }
+
+            if self.disk_history.is_some() {
+                let values = self.values_at(line);
+                let bytes = non_utf8.as_deref().unwrap_or(text.as_bytes());
+                if let Some(history) = &mut self.disk_history {
+                    history.append(line, time, offset, bytes, &values);
+                }
+                spilled = true;
+            }
+
+            for series in &mut self.graph_data {
+                let removed = series.remove_before(line + 1);
+                self.retained_bytes = self
+                    .retained_bytes
+                    .saturating_sub(removed * Series::SAMPLE_BYTES);
+            }
+        }
+
+        if spilled && let Some(history) = &mut self.disk_history {
+            history.flush();
+        }
+    }
+
+    /// 行番号`line`の行で値のある系列の(識別子, 値)。
+    fn values_at(&self, line: usize) -> Vec<(SeriesId, f64)> {
+        self.graph_data
+            .iter()
+            .enumerate()
+            .filter_map(|(i, series)| Some((self.series_id(i), series.get(line)?)))
+            .collect()
+    }
+
+    fn exceeds_retention(&self) -> bool {
+        match self.retention {
+            RetentionPolicy::Lines(lines) => self.timestamps.len() > lines,
+            RetentionPolicy::Duration(duration) => {
+                match (self.timestamps.front(), self.timestamps.back()) {
+                    (Some(&oldest), Some(&newest)) => newest - oldest > duration,
+                    _ => false,
+                }
+            }
+            RetentionPolicy::Megabytes(megabytes) => self.retained_bytes > megabytes * 1_000_000,
         }
     }
+}
+
+/// 1つのデータ系列。値のある行だけを(行番号, 値)として古い順に保持する。
+/// 値のない行は保持しないので、途中から現れた系列でも過去の行を埋める必要がない。
+#[derive(Clone, Debug, Default)]
+pub struct Series {
+    samples: VecDeque<(usize, f64)>,
+}
+
+impl Series {
+    /// 1つの値が使うメモリ量 [バイト]
+    pub const SAMPLE_BYTES: usize = size_of::<(usize, f64)>();
+
+    /// 値のある行の数。
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// 値を追加する。`line`はこれまでに追加した行番号より大きくなければならない。
+    pub fn push(&mut self, line: usize, value: f64) {
+        debug_assert!(self.samples.back().is_none_or(|&(last, _)| last < line));
+        self.samples.push_back((line, value));
+    }
+
+    /// 行番号`line`の値。
+    pub fn get(&self, line: usize) -> Option<f64> {
+        let index = self
+            .samples
+            .binary_search_by_key(&line, |&(line, _)| line)
+            .ok()?;
+        Some(self.samples[index].1)
+    }
+
+    /// 全ての(行番号, 値)を古い順に返す。
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (usize, f64)> + ExactSizeIterator + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// 行番号が`lines`の範囲にある(行番号, 値)を古い順に返す。
+    pub fn range(
+        &self,
+        lines: Range<usize>,
+    ) -> impl DoubleEndedIterator<Item = (usize, f64)> + ExactSizeIterator + '_ {
+        let start = self
+            .samples
+            .partition_point(|&(line, _)| line < lines.start);
+        let end = self.samples.partition_point(|&(line, _)| line < lines.end);
+        self.samples.range(start..end.max(start)).copied()
+    }
+
+    /// 行番号が`line`より前の値を捨て、捨てた数を返す。
+    pub fn remove_before(&mut self, line: usize) -> usize {
+        let count = self.samples.partition_point(|&(l, _)| l < line);
+        self.samples.drain(..count);
+        count
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// 他の系列から計算される系列。
+#[derive(Clone, Debug)]
+pub struct DerivedSeries {
+    /// 式の中で他の派生系列から参照するときの名前
+    pub name: String,
+    pub kind: DerivedKind,
+}
+
+#[derive(Clone, Debug)]
+pub enum DerivedKind {
+    /// 計算式。`source`はユーザーが入力した式
+    Expression { source: String, expression: Expr },
+    /// 他の系列にフィルタをかけたもの
+    Filter {
+        input: SeriesRef,
+        filter: SignalFilter,
+    },
+}
 
-    pub fn change_max_data_points(&mut self, new_max: usize) {
-        self.max_data_points = new_max;
-        // raw_dataとgraph_dataのサイズを調整
-        self.raw_data.truncate(new_max);
-        self.timestamps.truncate(new_max);
-        for series in &mut self.graph_data {
-            series.truncate(new_max);
+impl DerivedSeries {
+    /// 系列一覧に表示する説明
+    pub fn description(&self) -> String {
+        match &self.kind {
+            DerivedKind::Expression { source, .. } => source.clone(),
+            DerivedKind::Filter { input, filter } => {
+                let input = match input {
+                    SeriesRef::Index(index) => format!("${}", index + 1),
+                    SeriesRef::Name(name) => name.clone(),
+                };
+                format!("{} of {input}", filter.kind.label())
+            }
         }
     }
 }
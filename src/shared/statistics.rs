@@ -0,0 +1,78 @@
+// src/shared/statistics.rs
+
+use chrono::{DateTime, Utc};
+
+/// 1系列分の統計量を逐次的に集計する。
+/// 値を保持しないため、リングバッファから押し出されたデータも集計に残る。
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunningStats {
+    /// 有効な値の数
+    pub count: usize,
+    /// 値が`None`だった行の数
+    pub missing: usize,
+    pub min: f64,
+    pub max: f64,
+    pub last: Option<f64>,
+
+    // Welford法による平均と偏差平方和
+    mean: f64,
+    m2: f64,
+    sum_of_squares: f64,
+
+    first_time: Option<DateTime<Utc>>,
+    last_time: Option<DateTime<Utc>>,
+}
+
+impl RunningStats {
+    /// 既に`missing`行が過ぎた状態から集計を始める。
+    pub fn with_missing(missing: usize) -> Self {
+        Self {
+            missing,
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, value: Option<f64>, time: DateTime<Utc>) {
+        let Some(value) = value else {
+            self.missing += 1;
+            return;
+        };
+
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+            self.first_time = Some(time);
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.sum_of_squares += value * value;
+
+        self.last = Some(value);
+        self.last_time = Some(time);
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    pub fn rms(&self) -> Option<f64> {
+        (self.count > 0).then(|| (self.sum_of_squares / self.count as f64).sqrt())
+    }
+
+    /// 母標準偏差
+    pub fn std_dev(&self) -> Option<f64> {
+        (self.count > 0).then(|| (self.m2 / self.count as f64).sqrt())
+    }
+
+    /// 有効な値が届いた頻度 [Hz]
+    pub fn sample_rate(&self) -> Option<f64> {
+        let seconds = (self.last_time? - self.first_time?).num_microseconds()? as f64 / 1_000_000.0;
+        (seconds > 0.0).then(|| (self.count - 1) as f64 / seconds)
+    }
+}
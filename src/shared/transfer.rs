@@ -0,0 +1,157 @@
+// src/shared/transfer.rs
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use regex::Regex;
+
+/// ファイルを送信する単位。
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileSendUnit {
+    /// 1行ずつ。行末の改行コードは`line_ending`に置き換える
+    Lines { line_ending: Vec<u8> },
+    /// 一定のバイト数ずつ
+    Chunks(usize),
+}
+
+/// ファイルをシリアルポートに流し込む設定。
+#[derive(Clone, Debug)]
+pub struct FileSend {
+    pub path: PathBuf,
+    pub unit: FileSendUnit,
+    /// 1回送信するごとの待ち時間
+    pub delay: Duration,
+    /// 設定されていれば、送信するたびに一致する行を受信するまで次を送らない
+    pub wait_for: Option<Regex>,
+    /// 応答を待つ最大の時間
+    pub timeout: Duration,
+    /// 送信したデータをモニタに表示する
+    pub echo: bool,
+}
+
+impl FileSend {
+    /// ファイルの中身を送信する単位に分ける。
+    pub fn split(&self, contents: &[u8]) -> Vec<Vec<u8>> {
+        match &self.unit {
+            FileSendUnit::Lines { line_ending } => {
+                // 末尾の改行の後ろは空の行とみなさない
+                let contents = contents.strip_suffix(b"\n").unwrap_or(contents);
+                if contents.is_empty() {
+                    return Vec::new();
+                }
+                contents
+                    .split(|&byte| byte == b'\n')
+                    .map(|line| {
+                        let mut line = line.strip_suffix(b"\r").unwrap_or(line).to_vec();
+                        line.extend_from_slice(line_ending);
+                        line
+                    })
+                    .collect()
+            }
+            FileSendUnit::Chunks(size) => contents
+                .chunks((*size).max(1))
+                .map(<[u8]>::to_vec)
+                .collect(),
+        }
+    }
+}
+
+/// ブートローダなどにファイルを書き込むプロトコル。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModemProtocol {
+    /// 128バイトのブロックとCRC-16を使うXMODEM
+    XmodemCrc,
+    /// 1024バイトのブロックを使うXMODEM
+    Xmodem1k,
+    /// ファイル名とサイズを先に送る1024バイトのブロックのYMODEM
+    Ymodem,
+}
+
+impl ModemProtocol {
+    pub const ALL: [ModemProtocol; 3] = [
+        ModemProtocol::XmodemCrc,
+        ModemProtocol::Xmodem1k,
+        ModemProtocol::Ymodem,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ModemProtocol::XmodemCrc => "XMODEM-CRC",
+            ModemProtocol::Xmodem1k => "XMODEM-1K",
+            ModemProtocol::Ymodem => "YMODEM",
+        }
+    }
+}
+
+/// ポートを占有してプロトコルでファイルを送る設定。
+#[derive(Clone, Debug)]
+pub struct ModemSend {
+    pub path: PathBuf,
+    pub protocol: ModemProtocol,
+}
+
+/// ファイル転送の結果。
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransferState {
+    Running,
+    Done,
+    Cancelled,
+    Failed(String),
+}
+
+/// バックエンドで行っているファイル転送の進み具合。
+#[derive(Clone, Debug)]
+pub struct TransferProgress {
+    /// 転送しているファイルの名前
+    pub name: String,
+    /// 送信したバイト数と全体のバイト数
+    pub done: u64,
+    pub total: u64,
+    pub state: TransferState,
+}
+
+impl TransferProgress {
+    pub fn new(name: String, total: u64) -> Self {
+        Self {
+            name,
+            done: 0,
+            total,
+            state: TransferState::Running,
+        }
+    }
+
+    /// 0.0〜1.0の進み具合。
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.done as f32 / self.total as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let mut send = FileSend {
+            path: PathBuf::new(),
+            unit: FileSendUnit::Lines {
+                line_ending: b"\n".to_vec(),
+            },
+            delay: Duration::ZERO,
+            wait_for: None,
+            timeout: Duration::ZERO,
+            echo: false,
+        };
+        assert_eq!(
+            send.split(b"G28\r\nG1 X10\n\nM2\n"),
+            [&b"G28\n"[..], b"G1 X10\n", b"\n", b"M2\n"]
+        );
+        assert!(send.split(b"").is_empty());
+
+        send.unit = FileSendUnit::Chunks(4);
+        assert_eq!(send.split(b"0123456789"), [&b"0123"[..], b"4567", b"89"]);
+    }
+}